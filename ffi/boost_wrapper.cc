@@ -1,11 +1,31 @@
 #include <boost/math/special_functions/beta.hpp>
 
+#include <limits>
+#include <stdexcept>
+
+// Exceptions can't unwind through extern "C" into Rust, so errors are returned as values:
+// infinity when the result overflows, e.g. the density at 0 for a < 1, and NaN otherwise.
+template <typename F>
+static double no_throw(F f) {
+    try {
+        return f();
+    } catch (const std::overflow_error &) {
+        return std::numeric_limits<double>::infinity();
+    } catch (...) {
+        return std::numeric_limits<double>::quiet_NaN();
+    }
+}
+
 extern "C" {
     double boost_ibeta_inv(double a, double b, double p) {
-        return boost::math::ibeta_inv(a, b, p);
+        return no_throw([&] { return boost::math::ibeta_inv(a, b, p); });
     }
 
     double boost_ibeta(double a, double b, double p) {
-        return boost::math::ibeta(a, b, p);
+        return no_throw([&] { return boost::math::ibeta(a, b, p); });
+    }
+
+    double boost_ibeta_derivative(double a, double b, double x) {
+        return no_throw([&] { return boost::math::ibeta_derivative(a, b, x); });
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,6 @@
+#!/bin/bash
+# Prints more than a pipe buffer holds before reading the output it is given, then echoes it.
+
+head -c 200000 /dev/zero | tr '\0' 'x'
+echo
+cat
@@ -1 +1,2 @@
+#!/bin/sh
 exit $1
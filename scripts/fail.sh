@@ -0,0 +1,5 @@
+#!/bin/bash
+# Prints its arguments to stderr and fails.
+
+echo "$*" >&2
+exit 1
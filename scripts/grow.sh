@@ -0,0 +1,6 @@
+#!/bin/bash
+# Allocates memory a megabyte at a time until it runs out.
+
+chunk=$(head -c 1048576 /dev/zero | tr '\0' 'x')
+memory=()
+while :; do memory+=("$chunk"); done
@@ -0,0 +1,4 @@
+#!/bin/bash
+# Terminates itself with the given signal, e.g. SEGV or KILL.
+
+kill -$1 $$
@@ -0,0 +1,5 @@
+#!/bin/bash
+# Sleeps for the given number of seconds in a child process, then succeeds.
+
+sleep $1
+exit 0
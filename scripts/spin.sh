@@ -0,0 +1,4 @@
+#!/bin/bash
+# Burns CPU until killed.
+
+while :; do :; done
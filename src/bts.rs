@@ -1,43 +1,86 @@
 #![feature(test)]
 mod config;
+mod generators;
+mod history;
 mod ibeta;
+mod import;
 mod insights;
+mod limits;
+mod outcome;
+mod output;
+mod prior;
+mod report;
+mod runtime;
+mod selection;
+mod signatures;
+mod stop;
+mod tags;
 mod thompson;
 
 use argh::FromArgs;
 use config::{Config, Script};
-use insights::{plot_top_3, plot_top_3_inverses, print_ranking, print_ranking_bias_runtime};
+use history::Run;
+use insights::{
+    plot_history, plot_top, print_ranking, print_ranking_best_arm, print_ranking_bias_runtime,
+    print_signatures, print_stopped, Curve, PlotSelection,
+};
 use ordered_float::NotNan;
+use outcome::{is_valid_rule, judge, Termination, Verdict};
+use output::{best_arm_rows, print_rows, ranking_rows, summary_rows, NextScript, OutputFormat};
+use prior::{script_priors, Prior};
+use report::{Report, REPORT_SAMPLES};
+use runtime::{script_costs, script_runtimes, CostModel};
+use selection::{Candidates, ScriptId};
 use serde::{Deserialize, Serialize};
-use std::{process::Command, time::Instant};
-use thompson::{thompson_sampling, thompson_sampling_bias_runtime, ThompsonInfo};
+use signatures::record_signature;
+use std::{
+    fs,
+    io::{self, Read},
+    os::unix::process::{CommandExt, ExitStatusExt},
+    path::Path,
+    process::{Child, Command, ExitStatus, Output, Stdio},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+use tags::{BudgetUsage, TagFilter};
+use thompson::ThompsonInfo;
 
-use crate::config::{parse_config, save_config};
+use crate::config::{
+    config_dir, config_file_version, lock_config, parse_config, save_config, script_location,
+    CONFIG_VERSION,
+};
+use crate::import::{import_cargo_tests, list_cargo_tests, parse_script_list};
 
-fn choose_script(config: &Config, ignore_runtime: bool) -> usize {
-    let items = config
-        .scripts
-        .iter()
-        .filter(|x| x.limit.is_none() || x.limit.unwrap() < x.results.interesting)
-        .map(|x| &x.results)
-        .collect::<Vec<_>>();
-    let entries: &[&ThompsonInfo] = items.as_slice();
-    let runtime = config
-        .scripts
-        .iter()
-        .filter(|x| x.limit.is_none() || x.limit.unwrap() < x.results.interesting)
-        .map(|x| &x.avgruntime_ms)
-        .collect::<Vec<_>>();
-    let runtimes: &[&Option<NotNan<f64>>] = runtime.as_slice();
-
-    let user_biases = config.scripts.iter().map(|x| &x.bias).collect::<Vec<_>>();
-    let user_biases: &[&NotNan<f64>] = user_biases.as_slice();
+/// Choose the next script to run among the active scripts that match the filter and fit
+/// within their tag budgets. Budgets only hold scripts back while other scripts can run, so
+/// when every remaining script is over budget, one of them runs anyway.
+fn choose_script(
+    config: &Config,
+    ignore_runtime: bool,
+    filter: &TagFilter,
+    usage: &BudgetUsage,
+) -> Option<ScriptId> {
+    let runtimes = script_runtimes(config);
+    let priors = script_priors(&config.scripts, config.shared_prior);
+    let within_budget = Candidates::active(
+        &config.scripts,
+        &priors,
+        script_costs(config),
+        |ScriptId(index), script| {
+            let estimated_ms = runtimes[index].map_or(0.0, |runtime| runtime.median_ms());
+            filter.matches(script) && usage.allows(&config.budgets, script, estimated_ms)
+        },
+    );
 
-    if ignore_runtime {
-        thompson_sampling(entries, user_biases).unwrap()
-    } else {
-        thompson_sampling_bias_runtime(entries, runtimes, user_biases).unwrap()
-    }
+    within_budget.sample(ignore_runtime).or_else(|| {
+        Candidates::active(
+            &config.scripts,
+            &priors,
+            script_costs(config),
+            |_, script| filter.matches(script),
+        )
+        .sample(ignore_runtime)
+    })
 }
 
 fn run_script(script: &Script) -> ScriptResult {
@@ -50,30 +93,169 @@ fn run_script(script: &Script) -> ScriptResult {
 
     // Execute the command
     let start = Instant::now();
-    let output = Command::new(command)
-        .args(&args)
-        .output()
+    let mut cmd = Command::new(command);
+    cmd.args(&args).envs(&script.env);
+    if let Some(cwd) = &script.cwd {
+        cmd.current_dir(cwd);
+    }
+    if !script.limits.is_empty() {
+        let limits = script.limits;
+        // SAFETY: applying the limits only calls setrlimit and getrlimit, which are
+        // async-signal-safe.
+        unsafe {
+            cmd.pre_exec(move || limits.apply());
+        }
+    }
+
+    // Run the script in its own process group, so a timeout also kills anything it started.
+    let child = cmd
+        .process_group(0)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
         .expect("Failed to execute command");
-    let duration = start.elapsed();
+    let (output, timed_out, usage) =
+        wait_with_usage(child, script.timeout_ms.map(Duration::from_millis));
+    let runtime_ms = start.elapsed().as_micros() as f64 / 1000.0;
+
+    let termination = if timed_out {
+        println!(
+            "Command timed out after {}ms",
+            script.timeout_ms.unwrap_or_default()
+        );
+        Termination::Timeout
+    } else {
+        Termination::from_status(&output.status)
+    };
+
+    if let Some(limit) = script
+        .limits
+        .breached(&output.status, usage.cpu_ms, usage.max_rss_kb)
+        .filter(|_| !timed_out)
+    {
+        println!("Command exceeded its {} limit: {}", limit, output.status);
+        println!("Error: {}", String::from_utf8_lossy(&output.stderr));
+        return ScriptResult {
+            interesting: 0,
+            uninteresting: 0,
+            runtime_ms,
+            usage: Some(usage),
+            termination: Some(termination),
+            limit_breached: Some(limit),
+            signature: None,
+        };
+    }
 
     // Print the output
     if output.status.success() {
         println!("Command executed successfully!");
         println!("Output: {}", String::from_utf8_lossy(&output.stdout));
-        ScriptResult {
-            interesting: 0,
-            uninteresting: 1,
-            runtime_ms: duration.as_millis(),
-        }
     } else {
         println!("Command failed with error code: {}", output.status);
         println!("Error: {}", String::from_utf8_lossy(&output.stderr));
-        ScriptResult {
-            interesting: 1,
-            uninteresting: 0,
-            runtime_ms: duration.as_millis(),
+    }
+
+    let verdict = judge(&script.oracle, termination);
+    if verdict == Verdict::Error {
+        println!(
+            "{} counts as an error. The run is not counted as a result.",
+            termination
+        );
+    }
+
+    let signature = match (&script.signature, verdict) {
+        (Some(extractor), Verdict::Interesting) => extractor.extract(&output),
+        _ => None,
+    };
+
+    ScriptResult {
+        interesting: (verdict == Verdict::Interesting).into(),
+        uninteresting: (verdict == Verdict::Uninteresting).into(),
+        runtime_ms,
+        usage: Some(usage),
+        termination: Some(termination),
+        limit_breached: None,
+        signature,
+    }
+}
+
+/// Wait for the child to exit, killing its process group once the timeout elapses.
+/// Returns the collected output, whether the child was killed, and the resources it used.
+fn wait_with_usage(mut child: Child, timeout: Option<Duration>) -> (Output, bool, ResourceUsage) {
+    let stdout = child.stdout.take().map(read_pipe);
+    let stderr = child.stderr.take().map(read_pipe);
+    let pid = child.id() as libc::pid_t;
+
+    let start = Instant::now();
+    let mut timed_out = false;
+    let (status, usage) = loop {
+        let poll = timeout.is_some() && !timed_out;
+        if let Some(exited) = wait4(pid, poll) {
+            break exited;
+        }
+        if timeout.is_some_and(|timeout| start.elapsed() >= timeout) {
+            timed_out = true;
+            // The child leads its process group. Killing the group closes the pipes its
+            // children inherited, so reading the output doesn't wait on them.
+            // SAFETY: kill has no memory safety requirements.
+            if unsafe { libc::kill(-pid, libc::SIGKILL) } != 0 {
+                child.kill().ok();
+            }
+        } else {
+            thread::sleep(Duration::from_millis(1));
+        }
+    };
+
+    let collect = |pipe: Option<JoinHandle<Vec<u8>>>| {
+        pipe.map(|handle| handle.join().unwrap_or_default())
+            .unwrap_or_default()
+    };
+
+    (
+        Output {
+            status,
+            stdout: collect(stdout),
+            stderr: collect(stderr),
+        },
+        timed_out,
+        usage,
+    )
+}
+
+/// Reap the child with `wait4` to get its resource usage along with its exit status.
+/// With `poll`, returns `None` if the child is still running.
+fn wait4(pid: libc::pid_t, poll: bool) -> Option<(ExitStatus, ResourceUsage)> {
+    let options = if poll { libc::WNOHANG } else { 0 };
+    let mut status = 0;
+    // SAFETY: rusage is plain old data, and wait4 only writes to the pointers it is given.
+    let mut rusage: libc::rusage = unsafe { std::mem::zeroed() };
+    loop {
+        match unsafe { libc::wait4(pid, &mut status, options, &mut rusage) } {
+            0 => return None,
+            -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => continue,
+            -1 => panic!("Failed to wait on command: {}", io::Error::last_os_error()),
+            _ => break,
         }
     }
+
+    let ms = |time: libc::timeval| time.tv_sec as f64 * 1000.0 + time.tv_usec as f64 / 1000.0;
+    Some((
+        ExitStatus::from_raw(status),
+        ResourceUsage {
+            cpu_ms: ms(rusage.ru_utime) + ms(rusage.ru_stime),
+            // Linux reports kilobytes.
+            max_rss_kb: rusage.ru_maxrss.max(0) as u64,
+        },
+    ))
+}
+
+fn read_pipe<R: Read + Send + 'static>(mut pipe: R) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buffer = Vec::new();
+        pipe.read_to_end(&mut buffer).ok();
+        buffer
+    })
 }
 
 fn update_state(existing_results: &mut Script, result: ScriptResult) {
@@ -82,13 +264,50 @@ fn update_state(existing_results: &mut Script, result: ScriptResult) {
         uninteresting: existing_results.results.uninteresting + result.uninteresting,
     };
 
-    let total_runtime = existing_results
-        .avgruntime_ms
-        .unwrap_or(NotNan::new(0.0).unwrap())
-        * existing_results.runcount as f64;
+    // A run killed at its timeout only says the script takes at least that long, so it is
+    // left out of the average runtime and the runtime and resource models.
+    let timed_out = result.termination == Some(Termination::Timeout);
+    if !timed_out {
+        let measured_runs = existing_results
+            .runcount
+            .saturating_sub(existing_results.timeouts());
+        let total_runtime = existing_results
+            .avgruntime_ms
+            .unwrap_or(NotNan::new(0.0).unwrap())
+            * measured_runs as f64;
+        existing_results.avgruntime_ms =
+            Some((total_runtime + result.runtime_ms) / (measured_runs + 1) as f64);
+
+        let mut runtime = existing_results.runtime_model().unwrap_or_default();
+        runtime.update(result.runtime_ms);
+        existing_results.runtime = Some(runtime);
+    }
     existing_results.runcount += 1;
-    existing_results.avgruntime_ms =
-        Some((total_runtime + result.runtime_ms as f64) / existing_results.runcount as f64);
+
+    if let Some(usage) = result.usage.as_ref().filter(|_| !timed_out) {
+        let mut cpu_time = existing_results.cpu_time.unwrap_or_default();
+        cpu_time.update(usage.cpu_ms);
+        existing_results.cpu_time = Some(cpu_time);
+        let mut memory_mb_s = existing_results.memory_mb_s.unwrap_or_default();
+        memory_mb_s.update(usage.max_rss_kb as f64 / 1024.0 * result.runtime_ms / 1000.0);
+        existing_results.memory_mb_s = Some(memory_mb_s);
+        existing_results.max_rss_kb = existing_results.max_rss_kb.max(Some(usage.max_rss_kb));
+    }
+    if let Some(termination) = result.termination {
+        *existing_results
+            .outcomes
+            .entry(termination.to_string())
+            .or_default() += 1;
+    }
+    if let Some(limit) = result.limit_breached {
+        *existing_results
+            .limit_breaches
+            .entry(limit.to_string())
+            .or_default() += 1;
+    }
+    if let Some(signature) = result.signature {
+        *existing_results.signatures.entry(signature).or_default() += 1;
+    }
     existing_results.results = results;
 }
 
@@ -106,33 +325,160 @@ fn reset_state(config: &mut Config) {
             script.runcount = 0;
             script.results = results;
             script.avgruntime_ms = None;
+            script.runtime = None;
+            script.cpu_time = None;
+            script.memory_mb_s = None;
+            script.max_rss_kb = None;
+            script.limit_breaches.clear();
+            script.outcomes.clear();
+            script.signatures.clear();
             script
         })
         .collect();
+    config.signatures.clear();
+    config.history.clear();
+    config.history_dropped = 0;
 }
 
 #[derive(Debug)]
 struct ScriptResult {
     interesting: u64,
     uninteresting: u64,
-    runtime_ms: u128,
+    runtime_ms: f64,
+    /// Resources used by the run. Unknown for results recorded with `bts record`.
+    usage: Option<ResourceUsage>,
+    /// How the run ended. Unknown for results recorded with `bts record`.
+    termination: Option<Termination>,
+    /// The resource limit the run ran into, if any.
+    limit_breached: Option<&'static str>,
+    /// The failure signature of an interesting run, if the script has an extractor.
+    signature: Option<String>,
+}
+
+impl ScriptResult {
+    fn verdict(&self) -> Verdict {
+        if self.interesting > 0 {
+            Verdict::Interesting
+        } else if self.uninteresting > 0 {
+            Verdict::Uninteresting
+        } else {
+            Verdict::Error
+        }
+    }
 }
 
-fn step(config: &mut Config, ignore_runtime: bool) {
+/// Resources used by a finished script, as reported by `wait4`.
+#[derive(Debug, Default)]
+struct ResourceUsage {
+    cpu_ms: f64,
+    max_rss_kb: u64,
+}
+
+/// Keep the scripts matching the filter. Returns their priors and cost models, learned
+/// from every script in the config.
+fn filter_scripts(config: &mut Config, filter: &TagFilter) -> (Vec<Prior>, Vec<Option<CostModel>>) {
+    let priors = script_priors(&config.scripts, config.shared_prior);
+    let costs = script_costs(config);
+    let (scripts, estimates): (Vec<Script>, Vec<(Prior, Option<CostModel>)>) =
+        std::mem::take(&mut config.scripts)
+            .into_iter()
+            .zip(priors.into_iter().zip(costs))
+            .filter(|(script, _)| filter.matches(script))
+            .unzip();
+
+    config.scripts = scripts;
+    estimates.into_iter().unzip()
+}
+
+/// The curves `run` and `summarize` plot: the one asked for, or else the posterior density and,
+/// unless runtime is ignored, the density of the skewed score.
+fn plot_curves(plot: Option<Curve>, ignore_runtime: bool) -> Vec<Curve> {
+    match plot {
+        Some(curve) => vec![curve],
+        None if ignore_runtime => vec![Curve::Pdf],
+        None => vec![Curve::Pdf, Curve::Skewed],
+    }
+}
+
+/// Run one script. Returns false once there is nothing left to run.
+fn step(
+    config: &mut Config,
+    ignore_runtime: bool,
+    filter: &TagFilter,
+    usage: &mut BudgetUsage,
+) -> bool {
     if config.scripts.is_empty() {
         println!("ERROR: No scripts to execute. Exiting...");
-        return;
+        return false;
     }
 
-    let script_index = choose_script(config, ignore_runtime);
+    let Some(ScriptId(script_index)) = choose_script(config, ignore_runtime, filter, usage) else {
+        println!("No scripts left to execute. They are filtered out, retired or at their limit. Exiting...");
+        return false;
+    };
 
     println!("Running script {}...", script_index);
 
-    let result = run_script(&config.scripts[script_index]);
+    let mut result = run_script(&config.scripts[script_index]);
+
+    // Failures with a signature found before are a known bug, not an interesting case.
+    if let Some(signature) = &result.signature {
+        let name = &config.scripts[script_index].name;
+        if record_signature(&mut config.signatures, signature, name) {
+            println!("New failure signature: {}", signature);
+        } else {
+            println!(
+                "Known failure signature: {}. The run counts as uninteresting.",
+                signature
+            );
+            result.interesting = 0;
+            result.uninteresting = 1;
+        }
+    }
 
     println!("Script {} finished. Result: {:?}", script_index, result);
 
+    usage.record(&config.scripts[script_index], result.runtime_ms);
+    config.push_run(Run::new(
+        &config.scripts[script_index].name,
+        result.verdict(),
+        result.runtime_ms,
+    ));
     update_state(config.scripts.get_mut(script_index).unwrap(), result);
+    print_if_stopped(config, script_index);
+
+    true
+}
+
+/// Report a script that stopped after its latest result.
+fn print_if_stopped(config: &Config, script_index: usize) {
+    let priors = script_priors(&config.scripts, config.shared_prior);
+    let script = &config.scripts[script_index];
+    if let Some(reason) = script.stop_reason(&priors[script_index]) {
+        println!("{} stopped: {}", script.name, reason);
+    }
+}
+
+/// Choose up to `count` scripts to run next without running them. They come from a single
+/// ranking, so no script is chosen twice. Tag budgets only apply within a `bts run`, so they
+/// are ignored.
+fn next_scripts(
+    config: &Config,
+    ignore_runtime: bool,
+    filter: &TagFilter,
+    count: usize,
+) -> Vec<ScriptId> {
+    let priors = script_priors(&config.scripts, config.shared_prior);
+    let candidates = Candidates::active(
+        &config.scripts,
+        &priors,
+        script_costs(config),
+        |_, script| filter.matches(script),
+    );
+
+    let mut ranking = candidates.rank(ignore_runtime);
+    ranking.truncate(count);
+    ranking
 }
 
 #[derive(FromArgs, Debug)]
@@ -150,10 +496,17 @@ struct Args {
 enum SubCommands {
     New(NewOptions),
     Run(RunOptions),
+    Next(NextOptions),
+    Record(RecordOptions),
     Rank(RankOptions),
     Reset(ResetOptions),
     Summarize(SummarizeOptions),
+    HistoryPlot(HistoryPlotOptions),
+    Report(ReportOptions),
     Lint(LintOptions),
+    Import(ImportOptions),
+    Migrate(MigrateOptions),
+    Convert(ConvertOptions),
 }
 
 #[derive(Debug, Serialize, Deserialize, FromArgs, PartialEq)]
@@ -175,6 +528,84 @@ struct RunOptions {
     /// ignore runtime when ranking scripts
     #[argh(switch, short = 'i')]
     ignore_runtime: bool,
+
+    /// only include scripts with one of these tags
+    #[argh(option)]
+    tag: Vec<String>,
+
+    /// exclude scripts with any of these tags
+    #[argh(option)]
+    exclude_tag: Vec<String>,
+
+    /// number of scripts to plot
+    #[argh(option, default = "3")]
+    top: usize,
+
+    /// plot the most run scripts (runs), the best scoring (score) or those named *PATTERN* (name=PATTERN)
+    #[argh(option, default = "PlotSelection::Runs")]
+    plot_by: PlotSelection,
+
+    /// plot the posterior cdf, its pdf, or the pdf of the score skewed by runtime and bias
+    #[argh(option)]
+    plot: Option<Curve>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromArgs, PartialEq)]
+/// Print the next scripts to run without running them, for running them elsewhere
+#[argh(subcommand, name = "next")]
+struct NextOptions {
+    /// list of scripts to choose from
+    #[argh(positional)]
+    config: String,
+
+    /// number of scripts to choose
+    #[argh(option, short = 'n', default = "1")]
+    count: usize,
+
+    /// ignore runtime when ranking scripts
+    #[argh(switch, short = 'i')]
+    ignore_runtime: bool,
+
+    /// print the scripts as JSON, with their environment, directory and timeout
+    #[argh(switch)]
+    json: bool,
+
+    /// only include scripts with one of these tags
+    #[argh(option)]
+    tag: Vec<String>,
+
+    /// exclude scripts with any of these tags
+    #[argh(option)]
+    exclude_tag: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromArgs, PartialEq)]
+/// Record the result of a script that ran elsewhere
+#[argh(subcommand, name = "record")]
+struct RecordOptions {
+    /// config the script belongs to
+    #[argh(positional)]
+    config: String,
+
+    /// name of the script that ran
+    #[argh(positional)]
+    name: String,
+
+    /// the run was interesting
+    #[argh(switch)]
+    interesting: bool,
+
+    /// the run was uninteresting
+    #[argh(switch)]
+    uninteresting: bool,
+
+    /// wall-clock time the run took
+    #[argh(option)]
+    runtime_ms: f64,
+
+    /// output location for updated config. Defaults to updating the config in place
+    #[argh(option)]
+    output: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, FromArgs, PartialEq)]
@@ -188,6 +619,30 @@ struct SummarizeOptions {
     /// ignore runtime when ranking scripts
     #[argh(switch, short = 'i')]
     ignore_runtime: bool,
+
+    /// only include scripts with one of these tags
+    #[argh(option)]
+    tag: Vec<String>,
+
+    /// exclude scripts with any of these tags
+    #[argh(option)]
+    exclude_tag: Vec<String>,
+
+    /// print the ranking as text, json, csv or tsv
+    #[argh(option, default = "OutputFormat::Text")]
+    format: OutputFormat,
+
+    /// number of scripts to plot
+    #[argh(option, default = "3")]
+    top: usize,
+
+    /// plot the most run scripts (runs), the best scoring (score) or those named *PATTERN* (name=PATTERN)
+    #[argh(option, default = "PlotSelection::Runs")]
+    plot_by: PlotSelection,
+
+    /// plot the posterior cdf, its pdf, or the pdf of the score skewed by runtime and bias
+    #[argh(option)]
+    plot: Option<Curve>,
 }
 
 #[derive(Debug, Serialize, Deserialize, FromArgs, PartialEq)]
@@ -205,6 +660,34 @@ struct RankOptions {
     /// verbose
     #[argh(switch, short = 'v')]
     verbose: bool,
+
+    /// only include scripts with one of these tags
+    #[argh(option)]
+    tag: Vec<String>,
+
+    /// exclude scripts with any of these tags
+    #[argh(option)]
+    exclude_tag: Vec<String>,
+
+    /// print the ranking as text, json, csv or tsv
+    #[argh(option, default = "OutputFormat::Text")]
+    format: OutputFormat,
+
+    /// rank by the probability of being best, estimated from this many seeded posterior samples
+    #[argh(option)]
+    samples: Option<usize>,
+
+    /// number of scripts to plot
+    #[argh(option, default = "3")]
+    top: usize,
+
+    /// plot the most run scripts (runs), the best scoring (score) or those named *PATTERN* (name=PATTERN)
+    #[argh(option, default = "PlotSelection::Runs")]
+    plot_by: PlotSelection,
+
+    /// plot the posterior cdf, its pdf, or the pdf of the score skewed by runtime and bias
+    #[argh(option)]
+    plot: Option<Curve>,
 }
 
 #[derive(Debug, Serialize, Deserialize, FromArgs, PartialEq)]
@@ -228,18 +711,141 @@ struct NewOptions {
     #[argh(positional)]
     path: String,
 
-    /// test=command mapping
+    /// test=command mapping, split on the first '='
     #[argh(option, short = 't', from_str_fn(parse_mapping))]
     tests: Vec<(String, String)>,
+
+    /// import commands from a plain text (one command per line) or CSV
+    /// (name,command[,bias[,limit[,timeout_ms]]]) file
+    #[argh(option)]
+    from: Option<String>,
+
+    /// test=bias mapping, tests without a bias default to 1.0
+    #[argh(option, from_str_fn(parse_bias_mapping))]
+    bias: Vec<(String, NotNan<f64>)>,
+
+    /// test=limit mapping of interesting cases to collect before deactivating the test
+    #[argh(option, from_str_fn(parse_u64_mapping))]
+    limit: Vec<(String, u64)>,
+
+    /// test=milliseconds mapping after which a running test is killed
+    #[argh(option, from_str_fn(parse_u64_mapping))]
+    timeout_ms: Vec<(String, u64)>,
+}
+
+fn split_mapping(s: &str) -> Result<(&str, &str), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() && !value.is_empty() => Ok((key, value)),
+        _ => Err("Mapping should be in the format key=value".to_string()),
+    }
 }
 
 fn parse_mapping(s: &str) -> Result<(String, String), String> {
-    let parts: Vec<&str> = s.split('=').collect();
-    if parts.len() == 2 {
-        Ok((parts[0].to_string(), parts[1].to_string()))
-    } else {
-        Err("Mapping should be in the format key=value".to_string())
+    let (key, value) = split_mapping(s)?;
+    Ok((key.to_string(), value.to_string()))
+}
+
+fn parse_bias_mapping(s: &str) -> Result<(String, NotNan<f64>), String> {
+    let (key, value) = split_mapping(s)?;
+    let bias = value
+        .parse::<f64>()
+        .map_err(|e| format!("Invalid bias for {}: {}", key, e))?;
+    let bias = NotNan::new(bias).map_err(|_| format!("Bias for {} cannot be NaN", key))?;
+    Ok((key.to_string(), bias))
+}
+
+fn parse_u64_mapping(s: &str) -> Result<(String, u64), String> {
+    let (key, value) = split_mapping(s)?;
+    let value = value
+        .parse::<u64>()
+        .map_err(|e| format!("Invalid value for {}: {}", key, e))?;
+    Ok((key.to_string(), value))
+}
+
+/// Apply a per-test setting, reporting names that don't match any script.
+fn apply_mapping<T: Clone>(
+    scripts: &mut [Script],
+    mapping: &[(String, T)],
+    option: &str,
+    apply: impl Fn(&mut Script, T),
+) -> bool {
+    let mut valid = true;
+    for (name, value) in mapping {
+        match scripts.iter_mut().find(|script| &script.name == name) {
+            Some(script) => apply(script, value.clone()),
+            None => {
+                println!("ERROR: --{} refers to unknown test {}", option, name);
+                valid = false;
+            }
+        }
     }
+    valid
+}
+
+#[derive(Debug, Serialize, Deserialize, FromArgs, PartialEq)]
+/// Plot how scripts progressed over the run history
+#[argh(subcommand, name = "history-plot")]
+struct HistoryPlotOptions {
+    /// config with the run history
+    #[argh(positional)]
+    config: String,
+
+    /// only include scripts with one of these tags
+    #[argh(option)]
+    tag: Vec<String>,
+
+    /// exclude scripts with any of these tags
+    #[argh(option)]
+    exclude_tag: Vec<String>,
+
+    /// number of scripts to plot
+    #[argh(option, default = "3")]
+    top: usize,
+
+    /// plot the most run scripts (runs), the best scoring (score) or those named *PATTERN* (name=PATTERN)
+    #[argh(option, default = "PlotSelection::Runs")]
+    plot_by: PlotSelection,
+
+    /// plot against the time since the first run in the history instead of the run count
+    #[argh(switch)]
+    wall_clock: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromArgs, PartialEq)]
+/// Write a self-contained HTML report on the config
+#[argh(subcommand, name = "report")]
+struct ReportOptions {
+    /// config to report on
+    #[argh(positional)]
+    config: String,
+
+    /// output location for the HTML report
+    #[argh(option)]
+    html: String,
+
+    /// ignore runtime when ranking scripts
+    #[argh(switch, short = 'i')]
+    ignore_runtime: bool,
+
+    /// directory with a subdirectory of artifacts for each script, named after it
+    #[argh(option)]
+    artifacts: Option<String>,
+
+    /// only include scripts with one of these tags
+    #[argh(option)]
+    tag: Vec<String>,
+
+    /// exclude scripts with any of these tags
+    #[argh(option)]
+    exclude_tag: Vec<String>,
+
+    /// number of scripts to plot
+    #[argh(option, default = "3")]
+    top: usize,
+
+    /// plot the most run scripts (runs), the best scoring (score) or those named *PATTERN* (name=PATTERN)
+    #[argh(option, default = "PlotSelection::Runs")]
+    plot_by: PlotSelection,
 }
 
 #[derive(Debug, Serialize, Deserialize, FromArgs, PartialEq)]
@@ -251,75 +857,254 @@ struct LintOptions {
     config: String,
 }
 
+#[derive(Debug, Serialize, Deserialize, FromArgs, PartialEq)]
+/// Upgrade a config file written by an older version of bts
+#[argh(subcommand, name = "migrate")]
+struct MigrateOptions {
+    /// config to migrate
+    #[argh(positional)]
+    config: String,
+
+    /// output location for migrated config. Defaults to updating the config in place
+    #[argh(option)]
+    output: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromArgs, PartialEq)]
+/// Convert a config file between JSON, TOML and YAML. Formats are detected by extension
+#[argh(subcommand, name = "convert")]
+struct ConvertOptions {
+    /// config to convert
+    #[argh(positional)]
+    config: String,
+
+    /// output location for converted config
+    #[argh(positional)]
+    output: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromArgs, PartialEq)]
+/// Import scripts from an existing test suite
+#[argh(subcommand, name = "import")]
+struct ImportOptions {
+    #[argh(subcommand)]
+    source: ImportSources,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromArgs, PartialEq)]
+#[argh(subcommand)]
+enum ImportSources {
+    CargoTest(CargoTestImportOptions),
+}
+
+#[derive(Debug, Serialize, Deserialize, FromArgs, PartialEq)]
+/// Create one script per test listed by `cargo test -- --list --format terse`.
+/// Re-importing adds new tests and retires removed ones, keeping their results.
+#[argh(subcommand, name = "cargo-test")]
+struct CargoTestImportOptions {
+    /// config to import into. Created if it doesn't exist
+    #[argh(positional)]
+    config: String,
+
+    /// saved output of `cargo test -- --list --format terse` to read instead of running cargo
+    #[argh(option)]
+    list: Option<String>,
+
+    /// extra arguments passed to cargo test, e.g. "--release -p my-crate"
+    #[argh(option, default = "String::new()")]
+    cargo_args: String,
+
+    /// output location for updated config. Defaults to updating the config in place
+    #[argh(option)]
+    output: Option<String>,
+}
+
 fn main() {
     let args: Args = argh::from_env();
 
     match args.subcommand {
         SubCommands::New(new_opts) => {
+            let mut scripts = match &new_opts.from {
+                Some(path) => parse_script_list(path),
+                None => Vec::new(),
+            };
+            scripts.extend(new_opts.tests.iter().map(|(test_name, test_command)| {
+                Script::new(test_name.to_string(), test_command.to_string())
+            }));
+
+            let valid = [
+                apply_mapping(&mut scripts, &new_opts.bias, "bias", |script, bias| {
+                    script.bias = bias
+                }),
+                apply_mapping(&mut scripts, &new_opts.limit, "limit", |script, limit| {
+                    script.limit = Some(limit)
+                }),
+                apply_mapping(
+                    &mut scripts,
+                    &new_opts.timeout_ms,
+                    "timeout-ms",
+                    |script, timeout| script.timeout_ms = Some(timeout),
+                ),
+            ];
+            if valid.contains(&false) {
+                std::process::exit(1);
+            }
+
             let config = Config {
-                scripts: new_opts
-                    .tests
-                    .iter()
-                    .map(|(test_name, test_command)| Script {
-                        name: test_name.to_string(),
-                        command: test_command.to_string(),
-                        results: ThompsonInfo {
-                            interesting: 0,
-                            uninteresting: 0,
-                        },
-                        runcount: 0,
-                        avgruntime_ms: None,
-                        bias: NotNan::new(1.0).unwrap(),
-                        limit: None,
-                    })
-                    .collect(),
+                scripts,
+                ..Default::default()
             };
 
             save_config(&config, &new_opts.path);
         }
         SubCommands::Run(run_opts) => {
             let mut config = parse_config(&run_opts.config);
+            let filter = TagFilter {
+                tags: run_opts.tag,
+                exclude_tags: run_opts.exclude_tag,
+            };
+            let mut usage = BudgetUsage::default();
 
             for _ in 0..run_opts.steps {
-                step(&mut config, run_opts.ignore_runtime);
+                if !step(&mut config, run_opts.ignore_runtime, &filter, &mut usage) {
+                    break;
+                }
             }
 
             save_config(&config, &run_opts.output);
 
             let config = parse_config(&run_opts.output);
+            let priors = script_priors(&config.scripts, config.shared_prior);
+            let costs = script_costs(&config);
 
-            plot_top_3(&config.scripts);
-            if !run_opts.ignore_runtime {
-                plot_top_3_inverses(&config.scripts);
+            for curve in plot_curves(run_opts.plot, run_opts.ignore_runtime) {
+                plot_top(
+                    &config.scripts,
+                    &priors,
+                    &costs,
+                    run_opts.top,
+                    &run_opts.plot_by,
+                    curve,
+                );
             }
         }
-        SubCommands::Rank(rank_opts) => {
-            let config = parse_config(&rank_opts.config);
+        SubCommands::Next(next_opts) => {
+            let config = parse_config(&next_opts.config);
+            let filter = TagFilter {
+                tags: next_opts.tag,
+                exclude_tags: next_opts.exclude_tag,
+            };
 
-            if rank_opts.ignore_runtime {
-                print_ranking(&config.scripts, rank_opts.verbose);
+            let scripts = next_scripts(&config, next_opts.ignore_runtime, &filter, next_opts.count)
+                .into_iter()
+                .map(|ScriptId(index)| NextScript::from(&config.scripts[index]))
+                .collect::<Vec<_>>();
+
+            if next_opts.json {
+                println!("{}", serde_json::to_string_pretty(&scripts).unwrap());
             } else {
-                if rank_opts.verbose {
-                    plot_top_3_inverses(&config.scripts);
+                for script in scripts {
+                    println!("{}\t{}", script.name, script.command);
                 }
+            }
+        }
+        SubCommands::Record(record_opts) => {
+            let _lock = lock_config(&record_opts.config);
+            let mut config = parse_config(&record_opts.config);
 
-                let runtime = config
-                    .scripts
-                    .iter()
-                    .map(|x| &x.avgruntime_ms)
-                    .collect::<Vec<_>>();
-                let runtimes: &[&Option<NotNan<f64>>] = runtime.as_slice();
+            let (interesting, uninteresting) =
+                match (record_opts.interesting, record_opts.uninteresting) {
+                    (true, false) => (1, 0),
+                    (false, true) => (0, 1),
+                    _ => {
+                        println!("ERROR: Pass exactly one of --interesting and --uninteresting");
+                        std::process::exit(1);
+                    }
+                };
+            if !(record_opts.runtime_ms.is_finite() && record_opts.runtime_ms >= 0.0) {
+                println!("ERROR: --runtime-ms must be a finite number of at least 0");
+                std::process::exit(1);
+            }
+            let Some(script_index) = config
+                .scripts
+                .iter()
+                .position(|script| script.name == record_opts.name)
+            else {
+                println!("ERROR: Unknown script {}", record_opts.name);
+                std::process::exit(1);
+            };
+
+            let result = ScriptResult {
+                interesting,
+                uninteresting,
+                runtime_ms: record_opts.runtime_ms,
+                usage: None,
+                termination: None,
+                limit_breached: None,
+                signature: None,
+            };
+            config.push_run(Run::new(
+                &record_opts.name,
+                result.verdict(),
+                result.runtime_ms,
+            ));
+            update_state(&mut config.scripts[script_index], result);
+            print_if_stopped(&config, script_index);
 
-                let user_biases = config.scripts.iter().map(|x| &x.bias).collect::<Vec<_>>();
-                let user_biases: &[&NotNan<f64>] = user_biases.as_slice();
+            save_config(
+                &config,
+                record_opts.output.as_ref().unwrap_or(&record_opts.config),
+            );
+        }
+        SubCommands::Rank(rank_opts) => {
+            let mut config = parse_config(&rank_opts.config);
+            let filter = TagFilter {
+                tags: rank_opts.tag,
+                exclude_tags: rank_opts.exclude_tag,
+            };
+            let (priors, costs) = filter_scripts(&mut config, &filter);
 
-                print_ranking_bias_runtime(
+            if let Some(samples) = rank_opts.samples {
+                if samples == 0 {
+                    println!("ERROR: --samples must be at least 1");
+                    std::process::exit(1);
+                }
+
+                let ranking = Candidates::active(&config.scripts, &priors, costs, |_, _| true)
+                    .rank_by_best_arm(rank_opts.ignore_runtime, samples);
+                if rank_opts.format == OutputFormat::Text {
+                    print_ranking_best_arm(&config.scripts, &ranking, samples, rank_opts.verbose);
+                } else {
+                    print_rows(
+                        &best_arm_rows(&config.scripts, &priors, &ranking),
+                        rank_opts.format,
+                    );
+                }
+                return;
+            }
+
+            if rank_opts.format != OutputFormat::Text {
+                let rows = ranking_rows(&config.scripts, &priors, costs, rank_opts.ignore_runtime);
+                print_rows(&rows, rank_opts.format);
+                return;
+            }
+
+            if rank_opts.verbose && (rank_opts.plot.is_some() || !rank_opts.ignore_runtime) {
+                plot_top(
                     &config.scripts,
-                    runtimes,
-                    user_biases,
-                    rank_opts.verbose,
+                    &priors,
+                    &costs,
+                    rank_opts.top,
+                    &rank_opts.plot_by,
+                    rank_opts.plot.unwrap_or(Curve::Skewed),
                 );
             }
+
+            if rank_opts.ignore_runtime {
+                print_ranking(&config.scripts, &priors, rank_opts.verbose);
+            } else {
+                print_ranking_bias_runtime(&config.scripts, &priors, costs, rank_opts.verbose);
+            }
         }
         SubCommands::Reset(reset_opts) => {
             let mut config = parse_config(&reset_opts.config);
@@ -334,32 +1119,169 @@ fn main() {
             save_config(&config, &reset_opts.output);
         }
         SubCommands::Summarize(summarize_opts) => {
-            let config = parse_config(&summarize_opts.config);
+            let mut config = parse_config(&summarize_opts.config);
+            let filter = TagFilter {
+                tags: summarize_opts.tag,
+                exclude_tags: summarize_opts.exclude_tag,
+            };
+            let (priors, costs) = filter_scripts(&mut config, &filter);
 
-            plot_top_3(&config.scripts);
+            if summarize_opts.format != OutputFormat::Text {
+                let rows = summary_rows(
+                    &config.scripts,
+                    &priors,
+                    costs,
+                    summarize_opts.ignore_runtime,
+                );
+                print_rows(&rows, summarize_opts.format);
+                return;
+            }
+
+            for curve in plot_curves(summarize_opts.plot, summarize_opts.ignore_runtime) {
+                plot_top(
+                    &config.scripts,
+                    &priors,
+                    &costs,
+                    summarize_opts.top,
+                    &summarize_opts.plot_by,
+                    curve,
+                );
+            }
 
             if summarize_opts.ignore_runtime {
-                print_ranking(&config.scripts, true);
+                print_ranking(&config.scripts, &priors, true);
             } else {
-                plot_top_3_inverses(&config.scripts);
+                print_ranking_bias_runtime(&config.scripts, &priors, costs, true);
+            }
 
-                let runtime = config
-                    .scripts
-                    .iter()
-                    .map(|x| &x.avgruntime_ms)
-                    .collect::<Vec<_>>();
-                let runtimes: &[&Option<NotNan<f64>>] = runtime.as_slice();
+            print_stopped(&config.scripts, &priors);
+            print_signatures(&config.signatures);
+        }
+        SubCommands::HistoryPlot(history_opts) => {
+            let mut config = parse_config(&history_opts.config);
+            let filter = TagFilter {
+                tags: history_opts.tag,
+                exclude_tags: history_opts.exclude_tag,
+            };
+            let (priors, _) = filter_scripts(&mut config, &filter);
 
-                let user_biases = config.scripts.iter().map(|x| &x.bias).collect::<Vec<_>>();
-                let user_biases: &[&NotNan<f64>] = user_biases.as_slice();
+            plot_history(
+                &config.scripts,
+                &priors,
+                &config.history,
+                config.history_dropped,
+                history_opts.top,
+                &history_opts.plot_by,
+                history_opts.wall_clock,
+            );
+        }
+        SubCommands::Report(report_opts) => {
+            let mut config = parse_config(&report_opts.config);
+            let filter = TagFilter {
+                tags: report_opts.tag,
+                exclude_tags: report_opts.exclude_tag,
+            };
+            let (priors, costs) = filter_scripts(&mut config, &filter);
+            let ranking = Candidates::active(&config.scripts, &priors, costs, |_, _| true)
+                .rank_by_best_arm(report_opts.ignore_runtime, REPORT_SAMPLES);
 
-                print_ranking_bias_runtime(&config.scripts, runtimes, user_biases, true);
+            let report = Report {
+                title: &report_opts.config,
+                scripts: &config.scripts,
+                priors: &priors,
+                ranking: &ranking,
+                history: &config.history,
+                history_dropped: config.history_dropped,
+                signatures: &config.signatures,
+                artifacts: report_opts.artifacts.as_deref().map(Path::new),
+                html_dir: Path::new(&report_opts.html)
+                    .parent()
+                    .filter(|dir| !dir.as_os_str().is_empty())
+                    .unwrap_or(Path::new(".")),
+                top: report_opts.top,
+                selection: &report_opts.plot_by,
+            };
+            if let Err(err) = fs::write(&report_opts.html, report.to_string()) {
+                println!("ERROR: Failed to write {}: {}", report_opts.html, err);
+                std::process::exit(1);
             }
         }
         SubCommands::Lint(lint_opts) => {
             let config = parse_config(&lint_opts.config);
+            for generator in &config.generators {
+                if generator
+                    .expand(&config.defaults, config_dir(&lint_opts.config))
+                    .is_empty()
+                {
+                    println!(
+                        "{} Warning: Generator doesn't produce any scripts.",
+                        generator.name
+                    );
+                }
+            }
+
+            for (tag, budget) in &config.budgets {
+                if !config
+                    .scripts
+                    .iter()
+                    .any(|script| script.tags.contains(tag))
+                {
+                    println!(
+                        "{} Warning: No script has this tag. Its budget has no effect.",
+                        tag
+                    );
+                }
+                for share in [budget.max_run_share, budget.max_time_share]
+                    .into_iter()
+                    .flatten()
+                {
+                    if !(0.0..=1.0).contains(&share) {
+                        println!(
+                            "{} ERROR: Budget share {} must be between 0 and 1.",
+                            tag, share
+                        );
+                    }
+                }
+            }
+
+            for (resource, weight) in config.cost.weights() {
+                if weight < 0.0 {
+                    println!(
+                        "{:?} ERROR: A negative cost weight rewards scripts that use more of it.",
+                        resource
+                    );
+                }
+            }
+
+            if config.shared_prior {
+                for script in &config.scripts {
+                    // The script itself is left out of the prior, which needs two others.
+                    let siblings = config
+                        .scripts
+                        .iter()
+                        .filter(|other| other.group == script.group)
+                        .count()
+                        - 1;
+                    if siblings < 2 {
+                        println!("{} Warning: Fewer than two other scripts in its group. It will always use the uniform prior.", script.name);
+                    }
+                }
+            }
+
             let mut seen_zero = false;
-            for script in config.scripts {
+            for (index, script) in config.scripts.iter().enumerate() {
+                if let Some(other) = config.scripts[..index]
+                    .iter()
+                    .find(|other| other.name == script.name)
+                {
+                    println!(
+                        "{} ERROR: Duplicate script name in {} and {}. Results can't be attributed to the right script.",
+                        script.name,
+                        script_location(&lint_opts.config, other),
+                        script_location(&lint_opts.config, script)
+                    );
+                }
+
                 if script.bias == 0. {
                     println!("{} Warning: A bias of 0 will only run after all other scripts reach their limit.", script.name);
                     if seen_zero {
@@ -373,10 +1295,115 @@ fn main() {
                     println!("{} ERROR: A negative bias rewards tests that take more time to find an interesting case.", script.name);
                 }
 
+                if script.expected_runtime_ms.is_some_and(|value| value <= 0.) {
+                    println!(
+                        "{} ERROR: expected_runtime_ms must be greater than 0.",
+                        script.name
+                    );
+                }
+
                 if script.limit == Some(0) {
                     println!("{} Warning: Limit of 0. This will stop this script from ever running. Leave undefined to have no limit.", script.name)
                 }
+
+                for (rule, max) in [
+                    ("max_interesting", script.stop.max_interesting),
+                    ("max_runs", script.stop.max_runs),
+                    ("max_signatures", script.stop.max_signatures),
+                ] {
+                    if max == Some(0) {
+                        println!("{} Warning: A {} of 0 will stop this script from ever running. Leave undefined to have no limit.", script.name, rule);
+                    }
+                }
+                if script.stop.max_runtime_ms.is_some_and(|max| max <= 0.) {
+                    println!(
+                        "{} ERROR: max_runtime_ms must be greater than 0.",
+                        script.name
+                    );
+                }
+                if script
+                    .stop
+                    .failure_rate_below
+                    .is_some_and(|below| !(0.0..=1.0).contains(&below))
+                {
+                    println!(
+                        "{} ERROR: failure_rate_below must be between 0 and 1.",
+                        script.name
+                    );
+                }
+
+                for rule in script.oracle.keys() {
+                    if !is_valid_rule(rule) {
+                        println!("{} Warning: Oracle rule {} never matches. Use exit N, exit *, a signal name like SIGSEGV, signal N, signal * or timeout.", script.name, rule);
+                    }
+                }
+
+                for (field, limit) in [
+                    ("address_space_mb", script.limits.address_space_mb),
+                    ("cpu_seconds", script.limits.cpu_seconds),
+                    ("open_files", script.limits.open_files),
+                ] {
+                    if limit == Some(0) {
+                        println!(
+                            "{} ERROR: A {} limit of 0 stops the script from running at all.",
+                            script.name, field
+                        );
+                    }
+                }
+            }
+        }
+        SubCommands::Import(import_opts) => match import_opts.source {
+            ImportSources::CargoTest(cargo_opts) => {
+                let mut config = if Path::new(&cargo_opts.config).exists() {
+                    parse_config(&cargo_opts.config)
+                } else {
+                    Config::default()
+                };
+
+                let cargo_args = cargo_opts.cargo_args.split_whitespace().collect::<Vec<_>>();
+                let list = match &cargo_opts.list {
+                    Some(path) => fs::read_to_string(path).unwrap(),
+                    None => list_cargo_tests(&cargo_args),
+                };
+
+                let summary =
+                    import_cargo_tests(&mut config, &list, &cargo_args).unwrap_or_else(|err| {
+                        println!("ERROR: {}", err);
+                        std::process::exit(1);
+                    });
+                println!(
+                    "Added {} tests, retired {} tests, kept {} tests.",
+                    summary.added, summary.retired, summary.kept
+                );
+
+                save_config(
+                    &config,
+                    cargo_opts.output.as_ref().unwrap_or(&cargo_opts.config),
+                );
             }
+        },
+        SubCommands::Migrate(migrate_opts) => {
+            let version = config_file_version(&migrate_opts.config);
+            let config = parse_config(&migrate_opts.config);
+
+            save_config(
+                &config,
+                migrate_opts.output.as_ref().unwrap_or(&migrate_opts.config),
+            );
+
+            if version == CONFIG_VERSION {
+                println!("Config is already at version {}.", CONFIG_VERSION);
+            } else {
+                println!(
+                    "Migrated config from version {} to version {}.",
+                    version, CONFIG_VERSION
+                );
+            }
+        }
+        SubCommands::Convert(convert_opts) => {
+            let config = parse_config(&convert_opts.config);
+
+            save_config(&config, &convert_opts.output);
         }
     }
 }
@@ -1,40 +1,876 @@
 use std::{
-    fs::File,
+    collections::BTreeMap,
+    fs::{self, File},
     io::{Read, Write},
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
 };
 
 use ordered_float::NotNan;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-use crate::thompson::ThompsonInfo;
+use crate::{
+    generators::{expand_generators, Generator},
+    history::Run,
+    limits::ResourceLimits,
+    outcome::{Termination, Verdict},
+    prior::Prior,
+    runtime::{Cost, RuntimeModel},
+    signatures::{SignatureExtractor, SignatureRecord},
+    stop::StopRules,
+    tags::Budget,
+    thompson::ThompsonInfo,
+};
 
-pub fn parse_config(config_path: &String) -> Config {
+/// Version of the config format written by this build. Bump it whenever the format changes
+/// in a way older builds can't read, and add a migration from the previous version.
+pub const CONFIG_VERSION: u64 = 1;
+
+/// `MIGRATIONS[n]` upgrades a version `n` config to version `n + 1`.
+const MIGRATIONS: [fn(&mut Value); CONFIG_VERSION as usize] = [migrate_v0_to_v1];
+
+/// Version 0 configs predate the version field. Every field added since then has a default,
+/// so there is nothing to rewrite.
+fn migrate_v0_to_v1(_config: &mut Value) {}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl ConfigFormat {
+    /// Detect the format from the file extension. Unknown extensions are treated as JSON.
+    pub fn from_path(path: &str) -> ConfigFormat {
+        let extension = Path::new(path)
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("toml") => ConfigFormat::Toml,
+            Some("yaml") | Some("yml") => ConfigFormat::Yaml,
+            _ => ConfigFormat::Json,
+        }
+    }
+
+    fn deserialize(self, data: &str) -> Result<Value, String> {
+        match self {
+            ConfigFormat::Json => serde_json::from_str(data).map_err(|e| e.to_string()),
+            ConfigFormat::Toml => toml::from_str(data).map_err(|e| e.to_string()),
+            ConfigFormat::Yaml => serde_yaml::from_str(data).map_err(|e| e.to_string()),
+        }
+    }
+
+    fn serialize(self, config: &Value) -> Result<String, String> {
+        match self {
+            ConfigFormat::Json => serde_json::to_string_pretty(config).map_err(|e| e.to_string()),
+            ConfigFormat::Toml => {
+                // TOML has no null, leaving a key out is the closest equivalent.
+                let mut config = config.clone();
+                strip_nulls(&mut config);
+                toml::to_string_pretty(&config).map_err(|e| e.to_string())
+            }
+            ConfigFormat::Yaml => serde_yaml::to_string(config).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+fn strip_nulls(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            map.retain(|_, value| !value.is_null());
+            map.values_mut().for_each(strip_nulls);
+        }
+        Value::Array(values) => values.iter_mut().for_each(strip_nulls),
+        _ => {}
+    }
+}
+
+fn read_config_value(config_path: &String) -> Value {
     let mut file = File::open(config_path).unwrap();
     let mut data = String::new();
     file.read_to_string(&mut data).unwrap();
 
-    let config: Config = serde_json::from_str(&data).unwrap();
+    ConfigFormat::from_path(config_path)
+        .deserialize(&data)
+        .unwrap_or_else(|err| panic!("Failed to parse {}: {}", config_path, err))
+}
+
+fn value_version(config: &Value) -> u64 {
+    config.get("version").and_then(Value::as_u64).unwrap_or(0)
+}
+
+/// Version of the config file on disk, before any migrations.
+pub fn config_file_version(config_path: &String) -> u64 {
+    value_version(&read_config_value(config_path))
+}
+
+/// Upgrade a config to the current version in place.
+fn migrate(config: &mut Value) -> Result<(), String> {
+    let version = value_version(config);
+    if version > CONFIG_VERSION {
+        return Err(format!(
+            "Config version {} is newer than the newest version this build of bts supports ({}). Upgrade bts to use this config.",
+            version, CONFIG_VERSION
+        ));
+    }
+
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(config);
+    }
+    if let Some(config) = config.as_object_mut() {
+        config.insert("version".to_string(), Value::from(CONFIG_VERSION));
+    }
+
+    Ok(())
+}
+
+pub fn parse_config(config_path: &String) -> Config {
+    let mut loaded = vec![];
+    load_config_file(
+        config_path,
+        config_dir(config_path),
+        &Defaults::default(),
+        None,
+        &mut loaded,
+    )
+}
+
+/// Directory that paths in the config at `config_path` are relative to.
+pub fn config_dir(config_path: &str) -> &Path {
+    Path::new(config_path).parent().unwrap_or(Path::new(""))
+}
+
+/// Where the file included as `include` by the file at `source` lives, relative to the
+/// directory of the root config. `source` is `None` for the root config itself.
+fn include_source(source: Option<&String>, include: &str) -> String {
+    let dir = source.map_or(Path::new(""), |source| config_dir(source));
+    dir.join(include).to_string_lossy().to_string()
+}
+
+/// Path of a script's file, for messages about the config at `config_path`.
+pub fn script_location(config_path: &str, script: &Script) -> String {
+    match &script.source {
+        Some(source) => config_dir(config_path)
+            .join(source)
+            .to_string_lossy()
+            .to_string(),
+        None => config_path.to_string(),
+    }
+}
+
+/// Load a config and everything it includes.
+///
+/// Includes are relative to the directory of the including file. Scripts from included files
+/// are appended to `scripts` and remember which file they came from, relative to the root
+/// config's directory `root_dir`, so their state can be saved back there. `loaded` guards
+/// against include cycles.
+fn load_config_file(
+    config_path: &String,
+    root_dir: &Path,
+    parent_defaults: &Defaults,
+    source: Option<&String>,
+    loaded: &mut Vec<PathBuf>,
+) -> Config {
+    loaded.push(fs::canonicalize(config_path).unwrap_or_else(|_| PathBuf::from(config_path)));
+
+    let mut value = read_config_value(config_path);
+    if let Err(err) = migrate(&mut value) {
+        println!("ERROR: {}: {}", config_path, err);
+        std::process::exit(1);
+    }
+
+    let defaults: Defaults = value
+        .get("defaults")
+        .map(|defaults| serde_json::from_value(defaults.clone()).unwrap())
+        .unwrap_or_default();
+    let defaults = defaults.inherit(parent_defaults);
+
+    let inherited = value
+        .get_mut("scripts")
+        .and_then(Value::as_array_mut)
+        .map(|scripts| {
+            scripts
+                .iter_mut()
+                .filter_map(Value::as_object_mut)
+                .map(|script| defaults.apply(script))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    let mut config: Config = serde_json::from_value(value).unwrap();
+    for (script, inherited) in config.scripts.iter_mut().zip(inherited) {
+        script.inherited = inherited;
+    }
+    config.scripts = expand_generators(
+        &config.generators,
+        &defaults,
+        config_dir(config_path),
+        config.scripts,
+    );
+    for script in config.scripts.iter_mut() {
+        script.source = source.cloned();
+        if let Err(err) = script.validate() {
+            println!("ERROR: {}: {} {}", config_path, script.name, err);
+            std::process::exit(1);
+        }
+    }
+
+    for include in config.include.clone() {
+        let include_source = include_source(source, &include);
+        let include_path = root_dir.join(&include_source).to_string_lossy().to_string();
+        let canonical =
+            fs::canonicalize(&include_path).unwrap_or_else(|_| PathBuf::from(&include_path));
+        if loaded.contains(&canonical) {
+            println!(
+                "Warning: {} is included more than once. Skipping it.",
+                include_path
+            );
+            continue;
+        }
+
+        let mut included = load_config_file(
+            &include_path,
+            root_dir,
+            &defaults,
+            Some(&include_source),
+            loaded,
+        );
+        config.scripts.append(&mut included.scripts);
+        config.included.append(&mut included.included);
+        config.included.push((include_source, included));
+    }
+
     config
 }
 
+/// Take an exclusive lock on the config file, held until the returned file is dropped.
+/// Commands that update a config in place from outside a `bts run`, like `bts record`, hold
+/// it from loading the config until it is saved, so concurrent updates don't lose each
+/// other's results.
+pub fn lock_config(config_path: &String) -> File {
+    let file =
+        File::open(config_path).unwrap_or_else(|e| panic!("Failed to open {}: {}", config_path, e));
+    // SAFETY: flock only operates on the file descriptor, which stays open while it is locked.
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        panic!(
+            "Failed to lock {}: {}",
+            config_path,
+            std::io::Error::last_os_error()
+        );
+    }
+    file
+}
+
+/// Save the config in the format matching the extension of `path`.
+///
+/// Scripts from included files are saved back to the file they were loaded from, relative to
+/// the directory of `path`. Saving to another directory writes a copy of the included files
+/// there, so the saved config still includes them and the originals are left untouched.
 pub fn save_config(config: &Config, path: &String) {
-    let data = serde_json::to_string_pretty(config).unwrap();
+    write_config_file(config, &config.scripts, None, path);
+
+    for (source, included) in &config.included {
+        let include_path = config_dir(path).join(source);
+        write_config_file(
+            included,
+            &config.scripts,
+            Some(source),
+            &include_path.to_string_lossy().to_string(),
+        );
+    }
+}
+
+/// Write `header` with the scripts that were loaded from `source`.
+fn write_config_file(header: &Config, scripts: &[Script], source: Option<&String>, path: &String) {
+    let scripts = scripts
+        .iter()
+        .filter(|script| script.source.as_ref() == source)
+        .map(Script::to_value)
+        .collect();
+
+    let mut value = serde_json::to_value(header).unwrap();
+    value["scripts"] = Value::Array(scripts);
+
+    let data = ConfigFormat::from_path(path)
+        .serialize(&value)
+        .unwrap_or_else(|err| panic!("Failed to serialize config for {}: {}", path, err));
+    if let Some(dir) = Path::new(path)
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+    {
+        fs::create_dir_all(dir).unwrap();
+    }
     let mut file = File::create(path).unwrap();
     file.write_all(data.as_bytes()).unwrap();
 }
 
+/// Runs kept in the history of configs that don't set `history_limit`.
+pub const DEFAULT_HISTORY_LIMIT: usize = 10_000;
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Config {
+    #[serde(default)]
+    pub version: u64,
+    /// Other config files whose scripts are part of this suite.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    #[serde(default, skip_serializing_if = "Defaults::is_empty")]
+    pub defaults: Defaults,
+    /// Learn a prior for each group of scripts from the results of the scripts in it,
+    /// instead of starting every script from Beta(1, 1).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub shared_prior: bool,
+    /// Per-tag limits on how much of a run scripts with that tag may take.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub budgets: BTreeMap<String, Budget>,
+    /// Runtime assumed for scripts that haven't run yet, unless their tags or the script
+    /// itself say otherwise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_runtime_ms: Option<f64>,
+    /// Per-tag runtime assumed for scripts that haven't run yet.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tag_expected_runtime_ms: BTreeMap<String, f64>,
+    /// Resources that make up the cost of a run when biasing by runtime.
+    #[serde(default, skip_serializing_if = "Cost::is_default")]
+    pub cost: Cost,
+    /// Signature database: every failure signature found so far.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub signatures: BTreeMap<String, SignatureRecord>,
+    /// The latest runs, oldest first. Only the last `history_limit` runs are kept.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<Run>,
+    /// Most runs kept in `history`, [`DEFAULT_HISTORY_LIMIT`] unless set. Older runs are
+    /// dropped, so the config doesn't grow with every run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history_limit: Option<usize>,
+    /// Runs dropped from the start of `history` so far.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub history_dropped: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub generators: Vec<Generator>,
     pub scripts: Vec<Script>,
+    /// Every file included directly or indirectly, relative to the root config's directory,
+    /// with its scripts moved into `scripts`.
+    #[serde(skip)]
+    pub included: Vec<(String, Config)>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            version: CONFIG_VERSION,
+            include: vec![],
+            defaults: Defaults::default(),
+            shared_prior: false,
+            budgets: BTreeMap::new(),
+            expected_runtime_ms: None,
+            tag_expected_runtime_ms: BTreeMap::new(),
+            cost: Cost::default(),
+            signatures: BTreeMap::new(),
+            history: vec![],
+            history_limit: None,
+            history_dropped: 0,
+            generators: vec![],
+            scripts: vec![],
+            included: vec![],
+        }
+    }
+}
+
+impl Config {
+    /// Append `run` to the history, dropping the oldest runs beyond the history limit.
+    pub fn push_run(&mut self, run: Run) {
+        self.history.push(run);
+        let limit = self.history_limit.unwrap_or(DEFAULT_HISTORY_LIMIT);
+        let excess = self.history.len().saturating_sub(limit);
+        self.history.drain(..excess);
+        self.history_dropped += excess as u64;
+    }
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+/// Settings inherited by every script in the config, and in the configs it includes,
+/// unless the script sets them itself.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct Defaults {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bias: Option<NotNan<f64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    #[serde(default, skip_serializing_if = "ResourceLimits::is_empty")]
+    pub limits: ResourceLimits,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub oracle: BTreeMap<String, Verdict>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<SignatureExtractor>,
+    #[serde(default, skip_serializing_if = "StopRules::is_empty")]
+    pub stop: StopRules,
+}
+
+impl Defaults {
+    pub fn is_empty(&self) -> bool {
+        *self == Defaults::default()
+    }
+
+    /// These defaults, falling back to `parent` for anything they don't set.
+    pub fn inherit(&self, parent: &Defaults) -> Defaults {
+        let mut env = parent.env.clone();
+        env.extend(self.env.clone());
+        let mut oracle = parent.oracle.clone();
+        oracle.extend(self.oracle.clone());
+
+        Defaults {
+            bias: self.bias.or(parent.bias),
+            limit: self.limit.or(parent.limit),
+            timeout_ms: self.timeout_ms.or(parent.timeout_ms),
+            env,
+            cwd: self.cwd.clone().or_else(|| parent.cwd.clone()),
+            limits: self.limits.inherit(&parent.limits),
+            oracle,
+            signature: self.signature.clone().or_else(|| parent.signature.clone()),
+            stop: self.stop.inherit(&parent.stop),
+        }
+    }
+
+    /// Fill in the fields the script doesn't set. A null field counts as unset.
+    /// Returns the inherited fields, with `env.NAME`, `limits.NAME`, `oracle.NAME` and
+    /// `stop.NAME` for entries inherited into those maps.
+    fn apply(&self, script: &mut serde_json::Map<String, Value>) -> Vec<String> {
+        let mut inherited = vec![];
+        let fields = [
+            ("bias", self.bias.map(|bias| Value::from(f64::from(bias)))),
+            ("limit", self.limit.map(Value::from)),
+            ("timeout_ms", self.timeout_ms.map(Value::from)),
+            ("cwd", self.cwd.clone().map(Value::from)),
+            (
+                "signature",
+                self.signature
+                    .as_ref()
+                    .map(|signature| serde_json::to_value(signature).unwrap()),
+            ),
+        ];
+        for (field, default) in fields {
+            let Some(default) = default else {
+                continue;
+            };
+            if script.get(field).is_none_or(Value::is_null) {
+                script.insert(field.to_string(), default);
+                inherited.push(field.to_string());
+            }
+        }
+
+        let nested = [
+            ("env", serde_json::to_value(&self.env).unwrap()),
+            ("limits", serde_json::to_value(self.limits).unwrap()),
+            ("oracle", serde_json::to_value(&self.oracle).unwrap()),
+            ("stop", serde_json::to_value(self.stop).unwrap()),
+        ];
+        for (field, defaults) in nested {
+            let Some(defaults) = defaults.as_object().filter(|defaults| !defaults.is_empty())
+            else {
+                continue;
+            };
+            let values = script
+                .entry(field)
+                .or_insert_with(|| Value::Object(Default::default()));
+            if let Some(values) = values.as_object_mut() {
+                for (name, default) in defaults {
+                    if values.get(name).is_none_or(Value::is_null) {
+                        values.insert(name.clone(), default.clone());
+                        inherited.push(format!("{}.{}", field, name));
+                    }
+                }
+            }
+        }
+
+        inherited
+    }
+}
+
+fn default_bias() -> NotNan<f64> {
+    NotNan::new(1.0).unwrap()
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Script {
     pub name: String,
     pub command: String,
+    #[serde(default)]
     pub results: ThompsonInfo,
+    #[serde(default)]
     pub runcount: u64,
+    #[serde(default)]
     pub avgruntime_ms: Option<NotNan<f64>>,
+    /// Log-normal model of the runtime, used to sample runtimes when choosing scripts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runtime: Option<RuntimeModel>,
+    /// Runtime assumed until the script has run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_runtime_ms: Option<f64>,
+    /// Log-normal model of the user and system CPU time in ms.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_time: Option<RuntimeModel>,
+    /// Log-normal model of the peak resident memory in MB times the runtime in seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_mb_s: Option<RuntimeModel>,
+    /// Peak resident memory of any run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_rss_kb: Option<u64>,
+    #[serde(default = "default_bias")]
     pub bias: NotNan<f64>,
+    /// Interesting results to collect before the script stops. Same as `stop.max_interesting`.
+    #[serde(default)]
     pub limit: Option<u64>,
+    /// When the script stops running.
+    #[serde(default, skip_serializing_if = "StopRules::is_empty")]
+    pub stop: StopRules,
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Beta prior pseudo-count of interesting results, in place of the default of 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prior_alpha: Option<f64>,
+    /// Beta prior pseudo-count of uninteresting results, in place of the default of 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prior_beta: Option<f64>,
+    /// Scripts in the same group share a learned prior when `shared_prior` is on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// Name of the generator that produced this script, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generator: Option<String>,
+    /// Name of the test suite this script was imported from, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub imported_from: Option<String>,
+    /// Why this script no longer runs. Retired scripts keep their results.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retired: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Directory to run the command in. Defaults to the current directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    #[serde(default, skip_serializing_if = "ResourceLimits::is_empty")]
+    pub limits: ResourceLimits,
+    /// Runs that ran into a resource limit, by limit. They count as neither interesting nor
+    /// uninteresting.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub limit_breaches: BTreeMap<String, u64>,
+    /// What runs count as depending on how they end, e.g. `"SIGKILL": "error"`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub oracle: BTreeMap<String, Verdict>,
+    /// Runs by how they ended, e.g. `exit 0` or `SIGSEGV`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub outcomes: BTreeMap<String, u64>,
+    /// Extracts failure signatures, so only failures with a new signature count as interesting.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<SignatureExtractor>,
+    /// Failed runs by signature.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub signatures: BTreeMap<String, u64>,
+    /// Included config file this script was loaded from, relative to the root config's
+    /// directory. `None` for the root config.
+    #[serde(skip)]
+    pub source: Option<String>,
+    /// Fields filled in from `defaults`. They are left out when saving.
+    #[serde(skip)]
+    pub inherited: Vec<String>,
+}
+
+impl Script {
+    pub fn new(name: String, command: String) -> Script {
+        Script {
+            name,
+            command,
+            results: ThompsonInfo::default(),
+            runcount: 0,
+            avgruntime_ms: None,
+            runtime: None,
+            expected_runtime_ms: None,
+            cpu_time: None,
+            memory_mb_s: None,
+            max_rss_kb: None,
+            bias: default_bias(),
+            limit: None,
+            stop: StopRules::default(),
+            timeout_ms: None,
+            tags: vec![],
+            prior_alpha: None,
+            prior_beta: None,
+            group: None,
+            generator: None,
+            imported_from: None,
+            retired: None,
+            env: BTreeMap::new(),
+            cwd: None,
+            limits: ResourceLimits::default(),
+            limit_breaches: BTreeMap::new(),
+            oracle: BTreeMap::new(),
+            outcomes: BTreeMap::new(),
+            signature: None,
+            signatures: BTreeMap::new(),
+            source: None,
+            inherited: vec![],
+        }
+    }
+
+    /// Reject settings that can't be used at all, rather than failing in the middle of a run.
+    fn validate(&self) -> Result<(), String> {
+        for (field, value) in [
+            ("prior_alpha", self.prior_alpha),
+            ("prior_beta", self.prior_beta),
+        ] {
+            if value.is_some_and(|value| !(value > 0.0 && value.is_finite())) {
+                return Err(format!("{} must be a finite number greater than 0.", field));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The runtime model, seeded from `avgruntime_ms` as a single measurement for scripts
+    /// recorded before runtimes were modelled.
+    pub fn runtime_model(&self) -> Option<RuntimeModel> {
+        self.runtime.or_else(|| {
+            self.avgruntime_ms
+                .map(|avgruntime_ms| RuntimeModel::from_mean(f64::from(avgruntime_ms)))
+        })
+    }
+
+    /// Mean runtime of the runs that finished, from the runtime model. Runs killed at their
+    /// timeout aren't included.
+    pub fn mean_runtime_ms(&self) -> Option<f64> {
+        self.runtime_model().map(|model| model.mean_ms())
+    }
+
+    /// Runs killed at their timeout.
+    pub fn timeouts(&self) -> u64 {
+        self.outcomes
+            .get(&Termination::Timeout.to_string())
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Why the script no longer runs, or `None` if it is active. Scripts stop when they are
+    /// retired or meet one of their stop rules.
+    pub fn stop_reason(&self, prior: &Prior) -> Option<String> {
+        self.retired
+            .clone()
+            .or_else(|| self.stop.reason(self, prior))
+    }
+
+    /// Serialize the script, leaving out fields inherited from `defaults`.
+    fn to_value(&self) -> Value {
+        let mut value = serde_json::to_value(self).unwrap();
+        let Some(script) = value.as_object_mut() else {
+            return value;
+        };
+
+        for field in &self.inherited {
+            match field.split_once('.') {
+                Some((field, name)) => {
+                    if let Some(values) = script.get_mut(field).and_then(Value::as_object_mut) {
+                        values.remove(name);
+                    }
+                }
+                None => {
+                    script.remove(field);
+                }
+            }
+        }
+        for field in ["env", "limits", "oracle", "stop"] {
+            if script
+                .get(field)
+                .and_then(Value::as_object)
+                .is_some_and(|values| values.is_empty())
+            {
+                script.remove(field);
+            }
+        }
+
+        value
+    }
+}
+
+#[test]
+fn test_migrate_legacy_config() {
+    let mut config = serde_json::json!({
+        "scripts": [{ "name": "exit 0", "command": "./scripts/exit.sh 0" }]
+    });
+
+    migrate(&mut config).unwrap();
+    assert_eq!(value_version(&config), CONFIG_VERSION);
+
+    let config: Config = serde_json::from_value(config).unwrap();
+    assert_eq!(config.scripts[0].runcount, 0);
+    assert_eq!(config.scripts[0].bias, default_bias());
+}
+
+#[test]
+fn test_migrate_rejects_newer_config() {
+    let mut config = serde_json::json!({ "version": CONFIG_VERSION + 1, "scripts": [] });
+
+    assert!(migrate(&mut config).is_err());
+}
+
+#[test]
+fn test_config_format_from_path() {
+    assert_eq!(ConfigFormat::from_path("a/b.toml"), ConfigFormat::Toml);
+    assert_eq!(ConfigFormat::from_path("b.YML"), ConfigFormat::Yaml);
+    assert_eq!(ConfigFormat::from_path("b.yaml"), ConfigFormat::Yaml);
+    assert_eq!(
+        ConfigFormat::from_path("new-config.json"),
+        ConfigFormat::Json
+    );
+    assert_eq!(ConfigFormat::from_path("config"), ConfigFormat::Json);
+}
+
+#[test]
+fn test_config_formats_round_trip() {
+    let mut script = Script::new("a".to_string(), "./scripts/exit.sh 0".to_string());
+    script.results.interesting = 2;
+    script.results.uninteresting = 5;
+    script.runcount = 7;
+    script.avgruntime_ms = Some(NotNan::new(2.5).unwrap());
+    script.bias = NotNan::new(3.0).unwrap();
+    script.limit = Some(4);
+    script.timeout_ms = Some(100);
+    script.retired = Some("Gone".to_string());
+    let config = Config {
+        scripts: vec![script, Script::new("b".to_string(), "ls".to_string())],
+        ..Default::default()
+    };
+    let expected = serde_json::to_value(&config).unwrap();
+
+    for format in [ConfigFormat::Json, ConfigFormat::Toml, ConfigFormat::Yaml] {
+        let data = format.serialize(&expected).unwrap();
+        let value = format.deserialize(&data).unwrap();
+        let round_tripped: Config = serde_json::from_value(value).unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&round_tripped).unwrap(),
+            expected,
+            "{:?}",
+            format
+        );
+    }
+}
+
+#[test]
+fn test_defaults_inherit() {
+    let parent = Defaults {
+        bias: Some(NotNan::new(2.0).unwrap()),
+        limit: Some(5),
+        env: BTreeMap::from([
+            ("A".to_string(), "parent".to_string()),
+            ("B".to_string(), "parent".to_string()),
+        ]),
+        ..Default::default()
+    };
+    let child = Defaults {
+        limit: Some(1),
+        env: BTreeMap::from([("B".to_string(), "child".to_string())]),
+        ..Default::default()
+    };
+
+    let defaults = child.inherit(&parent);
+    assert_eq!(defaults.bias, parent.bias);
+    assert_eq!(defaults.limit, Some(1));
+    assert_eq!(defaults.env["A"], "parent");
+    assert_eq!(defaults.env["B"], "child");
+}
+
+#[test]
+fn test_defaults_apply_and_strip() {
+    let defaults = Defaults {
+        bias: Some(NotNan::new(2.0).unwrap()),
+        limit: Some(5),
+        env: BTreeMap::from([
+            ("A".to_string(), "default".to_string()),
+            ("B".to_string(), "default".to_string()),
+        ]),
+        ..Default::default()
+    };
+    let mut value = serde_json::json!({
+        "name": "a",
+        "command": "ls",
+        "limit": null,
+        "bias": 3.0,
+        "env": { "B": "script" }
+    });
+
+    let inherited = defaults.apply(value.as_object_mut().unwrap());
+    assert_eq!(inherited, vec!["limit", "env.A"]);
+
+    let mut script: Script = serde_json::from_value(value).unwrap();
+    assert_eq!(script.bias, NotNan::new(3.0).unwrap());
+    assert_eq!(script.limit, Some(5));
+    assert_eq!(script.env["A"], "default");
+    assert_eq!(script.env["B"], "script");
+
+    script.inherited = inherited;
+    let saved = script.to_value();
+    assert_eq!(saved["limit"], Value::Null);
+    assert_eq!(saved["bias"], 3.0);
+    assert_eq!(saved["env"], serde_json::json!({ "B": "script" }));
+}
+
+#[test]
+fn test_defaults_apply_limits() {
+    let defaults = Defaults {
+        limits: ResourceLimits {
+            address_space_mb: Some(1024),
+            cpu_seconds: Some(60),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut value = serde_json::json!({
+        "name": "a",
+        "command": "ls",
+        "limits": { "cpu_seconds": 5 }
+    });
+
+    let inherited = defaults.apply(value.as_object_mut().unwrap());
+    assert_eq!(inherited, vec!["limits.address_space_mb"]);
+
+    let mut script: Script = serde_json::from_value(value).unwrap();
+    assert_eq!(script.limits.address_space_mb, Some(1024));
+    assert_eq!(script.limits.cpu_seconds, Some(5));
+
+    script.inherited = inherited;
+    let saved = script.to_value();
+    assert_eq!(saved["limits"], serde_json::json!({ "cpu_seconds": 5 }));
+}
+
+#[test]
+fn test_push_run_drops_oldest_runs() {
+    let mut config = Config {
+        history_limit: Some(2),
+        ..Default::default()
+    };
+    for script in ["a", "b", "c"] {
+        config.push_run(Run::new(script, Verdict::Uninteresting, 1.0));
+    }
+
+    let kept = config
+        .history
+        .iter()
+        .map(|run| run.script.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(kept, vec!["b", "c"]);
+    assert_eq!(config.history_dropped, 1);
 }
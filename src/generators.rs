@@ -0,0 +1,293 @@
+use std::{collections::BTreeMap, fmt, path::Path};
+
+use ordered_float::NotNan;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Defaults, Script};
+
+/// Expands a command template into one script per glob match and matrix combination.
+///
+/// Templates may reference `{file}`, `{file_name}` and `{file_stem}` for the glob match,
+/// and `{key}` for every key in the matrix.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Generator {
+    /// Template for the generated script names. Also identifies the generator.
+    pub name: String,
+    pub command: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub glob: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub matrix: BTreeMap<String, Vec<MatrixValue>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bias: Option<NotNan<f64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_runtime_ms: Option<f64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(untagged)]
+pub enum MatrixValue {
+    Integer(i64),
+    Float(f64),
+    String(String),
+}
+
+impl fmt::Display for MatrixValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatrixValue::Integer(value) => write!(f, "{}", value),
+            MatrixValue::Float(value) => write!(f, "{}", value),
+            MatrixValue::String(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl Generator {
+    /// Every placeholder substitution this generator produces. A relative glob is resolved
+    /// against `dir`, the directory of the config that defines the generator.
+    fn substitutions(&self, dir: &Path) -> Vec<Vec<(String, String)>> {
+        let mut combinations: Vec<Vec<(String, String)>> = match &self.glob {
+            Some(pattern) => glob::glob(&dir.join(pattern).to_string_lossy())
+                .unwrap_or_else(|e| {
+                    panic!("Invalid glob {} in generator {}: {}", pattern, self.name, e)
+                })
+                .filter_map(Result::ok)
+                .map(|path| file_substitutions(&path))
+                .collect(),
+            None => vec![vec![]],
+        };
+
+        for (key, values) in &self.matrix {
+            combinations = combinations
+                .iter()
+                .flat_map(|combination| {
+                    values.iter().map(move |value| {
+                        let mut combination = combination.clone();
+                        combination.push((key.clone(), value.to_string()));
+                        combination
+                    })
+                })
+                .collect();
+        }
+
+        combinations
+    }
+
+    /// Scripts produced by this generator. Settings the generator leaves out come from `defaults`.
+    pub fn expand(&self, defaults: &Defaults, dir: &Path) -> Vec<Script> {
+        self.substitutions(dir)
+            .iter()
+            .map(|substitution| {
+                let mut script = Script::new(
+                    fill_template(&self.name, substitution),
+                    fill_template(&self.command, substitution),
+                );
+                script.generator = Some(self.name.clone());
+                if let Some(bias) = self.bias.or(defaults.bias) {
+                    script.bias = bias;
+                }
+                script.limit = self.limit.or(defaults.limit);
+                script.timeout_ms = self.timeout_ms.or(defaults.timeout_ms);
+                script.expected_runtime_ms = self.expected_runtime_ms;
+                script.tags = self.tags.clone();
+                script.group = self.group.clone();
+                script.env = defaults.env.clone();
+                script.cwd = defaults.cwd.clone();
+                script.limits = defaults.limits;
+                script.oracle = defaults.oracle.clone();
+                script.signature = defaults.signature.clone();
+                script.stop = defaults.stop;
+                script
+            })
+            .collect()
+    }
+}
+
+fn file_substitutions(path: &Path) -> Vec<(String, String)> {
+    let lossy = |x: Option<&std::ffi::OsStr>| x.unwrap_or_default().to_string_lossy().to_string();
+    vec![
+        ("file".to_string(), path.to_string_lossy().to_string()),
+        ("file_name".to_string(), lossy(path.file_name())),
+        ("file_stem".to_string(), lossy(path.file_stem())),
+    ]
+}
+
+/// Replace every `{key}` in the template in a single pass, so placeholders inside substituted
+/// values are left alone. Unknown placeholders are kept as they are.
+fn fill_template(template: &str, substitution: &[(String, String)]) -> String {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let value = rest.find('}').and_then(|end| {
+            substitution
+                .iter()
+                .find(|(key, _)| *key == rest[1..end])
+                .map(|(_, value)| (value, end))
+        });
+        match value {
+            Some((value, end)) => {
+                filled.push_str(value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                filled.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
+
+/// Replace previously generated scripts with a fresh expansion of the generators.
+///
+/// Scripts that are still generated keep their results and runtime. Scripts whose
+/// generator no longer produces them are dropped. Hand written scripts are untouched.
+pub fn expand_generators(
+    generators: &[Generator],
+    defaults: &Defaults,
+    dir: &Path,
+    scripts: Vec<Script>,
+) -> Vec<Script> {
+    let (mut expanded, mut previously_generated): (Vec<Script>, Vec<Script>) = scripts
+        .into_iter()
+        .partition(|script| script.generator.is_none());
+
+    for generator in generators {
+        for mut script in generator.expand(defaults, dir) {
+            if expanded.iter().any(|existing| existing.name == script.name) {
+                println!(
+                    "Warning: Generator {} produced duplicate script name {}. Skipping it.",
+                    generator.name, script.name
+                );
+                continue;
+            }
+
+            if let Some(index) = previously_generated
+                .iter()
+                .position(|existing| existing.name == script.name)
+            {
+                let existing = previously_generated.swap_remove(index);
+                script.results = existing.results;
+                script.runcount = existing.runcount;
+                script.avgruntime_ms = existing.avgruntime_ms;
+                script.runtime = existing.runtime;
+                script.cpu_time = existing.cpu_time;
+                script.memory_mb_s = existing.memory_mb_s;
+                script.max_rss_kb = existing.max_rss_kb;
+                script.limit_breaches = existing.limit_breaches;
+                script.outcomes = existing.outcomes;
+                script.signatures = existing.signatures;
+            }
+
+            expanded.push(script);
+        }
+    }
+
+    expanded
+}
+
+#[test]
+fn test_expand_matrix() {
+    let generator: Generator = serde_json::from_value(serde_json::json!({
+        "name": "test {p} {sleep}",
+        "command": "./scripts/bandit_example.sh {p} {sleep}",
+        "matrix": { "p": [0.2, 0.9], "sleep": [0] },
+        "limit": 3
+    }))
+    .unwrap();
+    let scripts = generator.expand(&Defaults::default(), Path::new(""));
+
+    assert_eq!(scripts.len(), 2);
+    assert_eq!(scripts[0].name, "test 0.2 0");
+    assert_eq!(scripts[1].command, "./scripts/bandit_example.sh 0.9 0");
+    assert_eq!(scripts[1].limit, Some(3));
+}
+
+#[test]
+fn test_expand_generators_preserves_state() {
+    let mut generator: Generator = serde_json::from_value(serde_json::json!({
+        "name": "test {p}",
+        "command": "./scripts/bandit_example.sh {p} 0",
+        "matrix": { "p": [0.2, 0.9] }
+    }))
+    .unwrap();
+    let mut scripts = expand_generators(
+        &[generator.clone()],
+        &Defaults::default(),
+        Path::new(""),
+        vec![],
+    );
+    scripts[1].runcount = 7;
+    scripts[1].results.interesting = 5;
+
+    generator.matrix.insert(
+        "p".to_string(),
+        vec![MatrixValue::Float(0.9), MatrixValue::Float(0.5)],
+    );
+    let scripts = expand_generators(&[generator], &Defaults::default(), Path::new(""), scripts);
+
+    assert_eq!(scripts.len(), 2);
+    assert_eq!(scripts[0].name, "test 0.9");
+    assert_eq!(scripts[0].runcount, 7);
+    assert_eq!(scripts[0].results.interesting, 5);
+    assert_eq!(scripts[1].name, "test 0.5");
+    assert_eq!(scripts[1].runcount, 0);
+}
+
+#[test]
+fn test_fill_template_single_pass() {
+    let substitution = vec![
+        ("file".to_string(), "{p}.txt".to_string()),
+        ("p".to_string(), "0.5".to_string()),
+    ];
+
+    assert_eq!(
+        fill_template("run {file} {p} {missing}", &substitution),
+        "run {p}.txt 0.5 {missing}"
+    );
+}
+
+#[test]
+fn test_removed_generator_drops_its_scripts() {
+    let generator: Generator = serde_json::from_value(serde_json::json!({
+        "name": "test {p}",
+        "command": "./scripts/bandit_example.sh {p} 0",
+        "matrix": { "p": [0.2, 0.9] }
+    }))
+    .unwrap();
+    let mut scripts = expand_generators(&[generator], &Defaults::default(), Path::new(""), vec![]);
+    scripts.push(Script::new("manual".to_string(), "./manual.sh".to_string()));
+
+    let scripts = expand_generators(&[], &Defaults::default(), Path::new(""), scripts);
+
+    assert_eq!(scripts.len(), 1);
+    assert_eq!(scripts[0].name, "manual");
+}
+
+#[test]
+fn test_glob_relative_to_config_dir() {
+    let generator: Generator = serde_json::from_value(serde_json::json!({
+        "name": "glob {file_stem}",
+        "command": "{file} 1",
+        "glob": "ex*.sh"
+    }))
+    .unwrap();
+
+    let scripts = generator.expand(&Defaults::default(), Path::new("scripts"));
+
+    assert_eq!(scripts.len(), 1);
+    assert_eq!(scripts[0].name, "glob exit");
+    assert_eq!(scripts[0].command, "scripts/exit.sh 1");
+}
@@ -0,0 +1,186 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Script,
+    outcome::Verdict,
+    prior::Prior,
+    thompson::{dist_area_at_percentile, ThompsonInfo},
+};
+
+/// One run, as kept in the config's history.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Run {
+    pub script: String,
+    pub verdict: Verdict,
+    pub runtime_ms: f64,
+    /// When the run finished, or was recorded with `bts record`, in milliseconds since the
+    /// Unix epoch.
+    pub finished_at_ms: u64,
+}
+
+impl Run {
+    pub fn new(script: &str, verdict: Verdict, runtime_ms: f64) -> Run {
+        Run {
+            script: script.to_string(),
+            verdict,
+            runtime_ms,
+            finished_at_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_millis() as u64),
+        }
+    }
+}
+
+/// How each of a set of scripts progressed over the history, sampled every so many runs.
+#[derive(Debug, Default, PartialEq)]
+pub struct Trajectories {
+    /// Runs so far at each sample, over all scripts.
+    pub runs: Vec<u64>,
+    /// When the run at each sample finished, in milliseconds since the Unix epoch.
+    pub finished_at_ms: Vec<u64>,
+    /// Per script, the interesting results found so far at each sample.
+    pub interesting: Vec<Vec<u64>>,
+    /// Per script, its share of the runs so far at each sample.
+    pub share: Vec<Vec<f64>>,
+    /// Per script, the median of its failure rate posterior at each sample.
+    pub median: Vec<Vec<f64>>,
+}
+
+/// Replay `history` for the scripts, taking up to `samples` evenly spaced samples along with
+/// the last run. Runs of other scripts count towards the total only.
+///
+/// When `dropped` runs were dropped from the start of the history, the replay starts from
+/// what those runs found: each script's totals less the runs still in the history.
+pub fn trajectories(
+    history: &[Run],
+    dropped: u64,
+    scripts: &[(&Script, &Prior)],
+    samples: usize,
+) -> Trajectories {
+    let mut trajectories = Trajectories {
+        interesting: vec![vec![]; scripts.len()],
+        share: vec![vec![]; scripts.len()],
+        median: vec![vec![]; scripts.len()],
+        ..Default::default()
+    };
+    let mut results = vec![ThompsonInfo::default(); scripts.len()];
+    let mut runcounts = vec![0u64; scripts.len()];
+    let position = |run: &Run| {
+        scripts
+            .iter()
+            .position(|(script, _)| script.name == run.script)
+    };
+    if dropped > 0 {
+        for (position, (script, _)) in scripts.iter().enumerate() {
+            results[position] = script.results.clone();
+            runcounts[position] = script.runcount;
+        }
+        for run in history {
+            if let Some(position) = position(run) {
+                let results = &mut results[position];
+                runcounts[position] = runcounts[position].saturating_sub(1);
+                match run.verdict {
+                    Verdict::Interesting => {
+                        results.interesting = results.interesting.saturating_sub(1)
+                    }
+                    Verdict::Uninteresting => {
+                        results.uninteresting = results.uninteresting.saturating_sub(1)
+                    }
+                    Verdict::Error => {}
+                }
+            }
+        }
+    }
+    let every = history.len().div_ceil(samples.max(1)).max(1);
+
+    for (index, run) in history.iter().enumerate() {
+        if let Some(position) = position(run) {
+            runcounts[position] += 1;
+            match run.verdict {
+                Verdict::Interesting => results[position].interesting += 1,
+                Verdict::Uninteresting => results[position].uninteresting += 1,
+                Verdict::Error => {}
+            }
+        }
+
+        if (index + 1) % every != 0 && index + 1 != history.len() {
+            continue;
+        }
+        let runs = dropped + index as u64 + 1;
+        trajectories.runs.push(runs);
+        trajectories.finished_at_ms.push(run.finished_at_ms);
+        for (position, (_, prior)) in scripts.iter().enumerate() {
+            trajectories.interesting[position].push(results[position].interesting);
+            trajectories.share[position].push(runcounts[position] as f64 / runs as f64);
+            trajectories.median[position].push(dist_area_at_percentile(
+                &results[position],
+                prior,
+                0.5,
+            ));
+        }
+    }
+
+    trajectories
+}
+
+#[test]
+fn test_trajectories() {
+    let scripts = [
+        Script::new("a".to_string(), "ls".to_string()),
+        Script::new("b".to_string(), "ls".to_string()),
+    ];
+    let prior = Prior::default();
+    let chosen = [(&scripts[0], &prior), (&scripts[1], &prior)];
+    let history = [
+        Run::new("a", Verdict::Interesting, 1.0),
+        Run::new("b", Verdict::Uninteresting, 1.0),
+        Run::new("other", Verdict::Interesting, 1.0),
+        Run::new("a", Verdict::Error, 1.0),
+        Run::new("a", Verdict::Interesting, 1.0),
+    ];
+
+    let all = trajectories(&history, 0, &chosen, 10);
+    assert_eq!(all.runs, vec![1, 2, 3, 4, 5]);
+    assert_eq!(all.interesting[0], vec![1, 1, 1, 1, 2]);
+    assert_eq!(all.interesting[1], vec![0, 0, 0, 0, 0]);
+    assert_eq!(all.share[0], vec![1.0, 0.5, 1.0 / 3.0, 0.5, 0.6]);
+    assert!(all.median[1][4] < 0.5);
+
+    // Every other run, and always the last one.
+    let sampled = trajectories(&history, 0, &chosen, 3);
+    assert_eq!(sampled.runs, vec![2, 4, 5]);
+    assert_eq!(sampled.interesting[0], vec![1, 1, 2]);
+
+    assert_eq!(
+        trajectories(&[], 0, &chosen, 10),
+        Trajectories {
+            interesting: vec![vec![], vec![]],
+            share: vec![vec![], vec![]],
+            median: vec![vec![], vec![]],
+            ..Default::default()
+        }
+    );
+}
+
+#[test]
+fn test_trajectories_after_dropped_runs() {
+    let mut script = Script::new("a".to_string(), "ls".to_string());
+    script.runcount = 4;
+    script.results.interesting = 2;
+    script.results.uninteresting = 2;
+    let prior = Prior::default();
+    let chosen = [(&script, &prior)];
+    // Two runs of a, one interesting, were dropped along with a run of another script.
+    let history = [
+        Run::new("a", Verdict::Interesting, 1.0),
+        Run::new("a", Verdict::Uninteresting, 1.0),
+    ];
+
+    let kept = trajectories(&history, 3, &chosen, 10);
+    assert_eq!(kept.runs, vec![4, 5]);
+    assert_eq!(kept.interesting[0], vec![2, 2]);
+    assert_eq!(kept.share[0], vec![0.75, 0.8]);
+    assert_eq!(kept.finished_at_ms.len(), 2);
+}
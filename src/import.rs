@@ -0,0 +1,291 @@
+use std::{fs::File, io::Read, path::Path, process::Command};
+
+use ordered_float::NotNan;
+
+use crate::config::{Config, Script};
+
+pub const CARGO_TEST_SUITE: &str = "cargo-test";
+
+/// Read a list of scripts from a plain text or CSV file.
+///
+/// Plain text files contain one command per line, and the command doubles as the script name.
+/// CSV files contain `name,command[,bias[,limit[,timeout_ms]]]` rows with an optional header.
+/// Blank lines and lines starting with `#` are ignored in both formats.
+pub fn parse_script_list(path: &String) -> Vec<Script> {
+    let mut file = File::open(path).unwrap();
+    let mut data = String::new();
+    file.read_to_string(&mut data).unwrap();
+
+    let is_csv = Path::new(path)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+
+    let lines = data
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+    if !is_csv {
+        return lines
+            .map(|(_, command)| Script::new(command.to_string(), command.to_string()))
+            .collect();
+    }
+
+    let mut scripts = Vec::new();
+    for (line_number, line) in lines {
+        let fields = split_csv_line(line);
+
+        if scripts.is_empty() && fields[0].eq_ignore_ascii_case("name") {
+            continue;
+        }
+
+        match script_from_csv_fields(&fields) {
+            Ok(script) => scripts.push(script),
+            Err(err) => panic!("{}:{}: {}", path, line_number, err),
+        }
+    }
+    scripts
+}
+
+fn script_from_csv_fields(fields: &[String]) -> Result<Script, String> {
+    if fields.len() < 2 || fields[0].is_empty() || fields[1].is_empty() {
+        return Err("Expected at least a name and a command".to_string());
+    }
+    if fields.len() > 5 {
+        return Err("Expected at most name,command,bias,limit,timeout_ms".to_string());
+    }
+
+    let mut script = Script::new(fields[0].clone(), fields[1].clone());
+
+    if let Some(bias) = fields.get(2).filter(|x| !x.is_empty()) {
+        let bias = bias
+            .parse::<f64>()
+            .map_err(|e| format!("Invalid bias {}: {}", bias, e))?;
+        script.bias = NotNan::new(bias).map_err(|_| "Bias cannot be NaN".to_string())?;
+    }
+    if let Some(limit) = fields.get(3).filter(|x| !x.is_empty()) {
+        script.limit = Some(
+            limit
+                .parse()
+                .map_err(|e| format!("Invalid limit {}: {}", limit, e))?,
+        );
+    }
+    if let Some(timeout) = fields.get(4).filter(|x| !x.is_empty()) {
+        script.timeout_ms = Some(
+            timeout
+                .parse()
+                .map_err(|e| format!("Invalid timeout {}: {}", timeout, e))?,
+        );
+    }
+
+    Ok(script)
+}
+
+/// Split a CSV line into trimmed fields. Fields wrapped in double quotes may contain commas,
+/// and a doubled quote inside a quoted field is an escaped quote.
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => {
+                fields.push(field.trim().to_string());
+                field.clear();
+            }
+            _ => field.push(c),
+        }
+    }
+    fields.push(field.trim().to_string());
+
+    fields
+}
+
+/// Run `cargo test -- --list --format terse` and return its output.
+pub fn list_cargo_tests(cargo_args: &[&str]) -> String {
+    let output = Command::new("cargo")
+        .arg("test")
+        .args(cargo_args)
+        .args(["--", "--list", "--format", "terse"])
+        .output()
+        .expect("Failed to execute cargo");
+
+    if !output.status.success() {
+        println!("Error: {}", String::from_utf8_lossy(&output.stderr));
+        panic!("Failed to list cargo tests");
+    }
+
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+/// Test names from the terse listing. Benchmarks and names the command line can't
+/// pass through as a single argument are skipped.
+fn parse_cargo_test_list(list: &str) -> Vec<String> {
+    let mut tests = Vec::new();
+    for line in list.lines() {
+        let Some(name) = line.trim().strip_suffix(": test") else {
+            continue;
+        };
+
+        if name.contains(char::is_whitespace) {
+            println!(
+                "Warning: Skipping {}. Test names with whitespace aren't supported.",
+                name
+            );
+            continue;
+        }
+
+        tests.push(name.to_string());
+    }
+    tests
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ImportSummary {
+    pub added: usize,
+    pub retired: usize,
+    pub kept: usize,
+}
+
+/// Sync the config with the tests in a `cargo test -- --list --format terse` listing.
+///
+/// New tests are added, tests that disappeared are retired and tests that were retired
+/// but are listed again are reactivated. Results are never discarded.
+///
+/// Scripts are run by splitting their command on whitespace, so cargo arguments containing
+/// whitespace can't be passed on and are an error.
+pub fn import_cargo_tests(
+    config: &mut Config,
+    list: &str,
+    cargo_args: &[&str],
+) -> Result<ImportSummary, String> {
+    if let Some(arg) = cargo_args
+        .iter()
+        .find(|arg| arg.contains(char::is_whitespace))
+    {
+        return Err(format!(
+            "Cargo argument {:?} contains whitespace, which isn't supported.",
+            arg
+        ));
+    }
+
+    let tests = parse_cargo_test_list(list);
+    let mut summary = ImportSummary::default();
+
+    for script in config
+        .scripts
+        .iter_mut()
+        .filter(|script| script.imported_from.as_deref() == Some(CARGO_TEST_SUITE))
+    {
+        if tests.contains(&script.name) {
+            script.retired = None;
+        } else if script.retired.is_none() {
+            script.retired = Some("No longer listed by cargo test".to_string());
+            summary.retired += 1;
+        }
+    }
+
+    for test in tests {
+        let command = cargo_test_command(&test, cargo_args);
+        match config.scripts.iter_mut().find(|script| script.name == test) {
+            Some(script) if script.imported_from.as_deref() == Some(CARGO_TEST_SUITE) => {
+                script.command = command;
+                summary.kept += 1;
+            }
+            Some(_) => {
+                println!(
+                    "Warning: Skipping {}. A script with this name already exists.",
+                    test
+                );
+            }
+            None => {
+                let mut script = Script::new(test, command);
+                script.imported_from = Some(CARGO_TEST_SUITE.to_string());
+                config.scripts.push(script);
+                summary.added += 1;
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+fn cargo_test_command(test: &str, cargo_args: &[&str]) -> String {
+    let mut command = vec!["cargo", "test"];
+    command.extend(cargo_args);
+    command.extend(["--", "--exact", test]);
+    command.join(" ")
+}
+
+#[test]
+fn test_import_cargo_tests_rejects_whitespace_args() {
+    let mut config = Config::default();
+
+    let result = import_cargo_tests(&mut config, "a: test\n", &["--features", "x y"]);
+
+    assert!(result.is_err());
+    assert!(config.scripts.is_empty());
+}
+
+#[test]
+fn test_split_csv_line_quoted() {
+    assert_eq!(
+        split_csv_line(r#"lvl 3,"./fuzz --level=3, --seed ""x""",2"#),
+        vec!["lvl 3", r#"./fuzz --level=3, --seed "x""#, "2"]
+    );
+}
+
+#[test]
+fn test_script_from_csv_fields() {
+    let fields = split_csv_line("a,./a.sh,2.5,,100");
+    let script = script_from_csv_fields(&fields).unwrap();
+    assert_eq!(script.name, "a");
+    assert_eq!(script.command, "./a.sh");
+    assert_eq!(script.bias, NotNan::new(2.5).unwrap());
+    assert_eq!(script.limit, None);
+    assert_eq!(script.timeout_ms, Some(100));
+}
+
+#[test]
+fn test_import_cargo_tests_retires_removed() {
+    let mut config = Config::default();
+
+    let list = "thompson::a: test\nthompson::b: test\nbench_a: benchmark\n";
+    let summary = import_cargo_tests(&mut config, list, &["--release"]).unwrap();
+    assert_eq!(
+        summary,
+        ImportSummary {
+            added: 2,
+            retired: 0,
+            kept: 0
+        }
+    );
+    assert_eq!(
+        config.scripts[0].command,
+        "cargo test --release -- --exact thompson::a"
+    );
+
+    config.scripts[0].runcount = 3;
+    let summary =
+        import_cargo_tests(&mut config, "thompson::a: test\nthompson::c: test\n", &[]).unwrap();
+    assert_eq!(
+        summary,
+        ImportSummary {
+            added: 1,
+            retired: 1,
+            kept: 1
+        }
+    );
+    assert_eq!(config.scripts[0].runcount, 3);
+    assert_eq!(config.scripts[0].retired, None);
+    assert!(config.scripts[1].retired.is_some());
+    assert_eq!(config.scripts[2].name, "thompson::c");
+}
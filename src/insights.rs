@@ -1,309 +1,793 @@
-use std::ffi::c_double;
+use std::{collections::BTreeMap, ffi::c_double, fmt, str::FromStr};
 
 use colored::Colorize;
 use ordered_float::NotNan;
+use rand::{rngs::StdRng, SeedableRng};
 use rgb::RGB8;
+use serde::{Deserialize, Serialize};
 use textplots::ColorPlot;
 
 use crate::{
-    thompson::{
-        dist_area_at_percentile, skew_percentile, thompson_ranking, thompson_ranking_bias_runtime,
-        ThompsonInfo,
-    },
+    history::{trajectories, Run},
+    prior::Prior,
+    runtime::CostModel,
+    selection::{BestArmEstimate, Candidates, ScriptId},
+    signatures::SignatureRecord,
+    thompson::{skew_percentile, thompson_scores_bias_runtime, PosteriorSummary},
     Script,
 };
 
 extern "C" {
     fn boost_ibeta(a: c_double, b: c_double, p: c_double) -> c_double;
     fn boost_ibeta_inv(a: c_double, b: c_double, p: c_double) -> c_double;
+    fn boost_ibeta_derivative(a: c_double, b: c_double, x: c_double) -> c_double;
 }
 
-pub fn plot_top_3(scripts: &[Script]) {
-    if scripts.len() < 3 {
-        println!("Cannot plot top 3 with less than 3 scripts.");
-        return;
+/// Which scripts to plot.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PlotSelection {
+    /// The most run scripts.
+    #[default]
+    Runs,
+    /// The scripts with the highest posterior mean, adjusted for runtime and bias.
+    Score,
+    /// The most run scripts whose name contains the pattern.
+    Name(String),
+}
+
+impl FromStr for PlotSelection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some(("name", pattern)) => Ok(PlotSelection::Name(pattern.to_string())),
+            None if s == "runs" => Ok(PlotSelection::Runs),
+            None if s == "score" => Ok(PlotSelection::Score),
+            _ => Err(format!(
+                "Unknown plot selection {}. Use runs, score or name=PATTERN.",
+                s
+            )),
+        }
     }
+}
 
-    let mut scripts = scripts.to_owned();
+impl fmt::Display for PlotSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlotSelection::Runs => write!(f, "runs"),
+            PlotSelection::Score => write!(f, "score"),
+            PlotSelection::Name(pattern) => write!(f, "runs, named *{}*", pattern),
+        }
+    }
+}
 
-    scripts.sort_by(|a, b| b.runcount.partial_cmp(&a.runcount).unwrap());
+/// The curve drawn for each plotted script.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Curve {
+    /// Cumulative distribution of the failure rate posterior.
+    Cdf,
+    /// Density of the failure rate posterior.
+    #[default]
+    Pdf,
+    /// Density of the score scripts are ranked by: the failure rate skewed by the sampled cost
+    /// and the bias. Estimated from a histogram of sampled scores.
+    Skewed,
+}
 
-    let most_run_scripts: Vec<&Script> = scripts.iter().take(3).collect();
+impl FromStr for Curve {
+    type Err = String;
 
-    use textplots::{Chart, Shape};
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cdf" => Ok(Curve::Cdf),
+            "pdf" => Ok(Curve::Pdf),
+            "skewed" => Ok(Curve::Skewed),
+            _ => Err(format!("Unknown plot {}. Use cdf, pdf or skewed.", s)),
+        }
+    }
+}
 
-    println!("Plot of top 3 run scripts. Interesting cases (area under curve).");
+/// Most samples taken from the run history for each line in a history plot, so long histories
+/// plot as quickly as short ones.
+pub const HISTORY_SAMPLES: usize = 240;
 
-    let colors = [
-        RGB8 {
-            r: 100,
-            g: 250,
-            b: 200,
-        },
-        RGB8 {
-            r: 200,
-            g: 250,
-            b: 100,
-        },
-        RGB8 {
-            r: 200,
-            g: 100,
-            b: 250,
-        },
-    ];
+/// Points drawn for each continuous curve.
+const PLOT_POINTS: usize = 240;
 
-    Chart::new(120, 60, 0.0, 1.0)
-        .linecolorplot(
-            &Shape::Continuous(Box::new(|x| unsafe {
-                boost_ibeta(
-                    (most_run_scripts[2].results.uninteresting + 1) as f64,
-                    (most_run_scripts[2].results.interesting + 1) as f64,
-                    x.into(),
-                ) as f32
-            })),
-            colors[2],
-        )
-        .linecolorplot(
-            &Shape::Continuous(Box::new(|x| unsafe {
-                boost_ibeta(
-                    (most_run_scripts[1].results.uninteresting + 1) as f64,
-                    (most_run_scripts[1].results.interesting + 1) as f64,
-                    x.into(),
-                ) as f32
-            })),
-            colors[1],
-        )
-        .linecolorplot(
-            &Shape::Continuous(Box::new(|x| unsafe {
-                boost_ibeta(
-                    (most_run_scripts[0].results.uninteresting + 1) as f64,
-                    (most_run_scripts[0].results.interesting + 1) as f64,
-                    x.into(),
-                ) as f32
-            })),
-            colors[0],
-        )
-        .display();
+/// Draws per script for the score density of [`Curve::Skewed`], and the histogram bins they
+/// are counted in.
+const SCORE_SAMPLES: usize = 4000;
+const SCORE_BINS: usize = 60;
 
-    println!("Top 3 run scripts:");
-    println!(
-        "1: {} {} {}ms",
-        most_run_scripts[0].runcount,
-        most_run_scripts[0]
-            .name
-            .truecolor(colors[0].r, colors[0].g, colors[0].b),
-        most_run_scripts[0]
-            .avgruntime_ms
-            .unwrap_or(NotNan::new(-1.0).unwrap())
-    );
-    println!(
-        "2: {} {} {}ms",
-        most_run_scripts[1].runcount,
-        most_run_scripts[1]
-            .name
-            .truecolor(colors[1].r, colors[1].g, colors[1].b),
-        most_run_scripts[1]
-            .avgruntime_ms
-            .unwrap_or(NotNan::new(-1.0).unwrap())
-    );
-    println!(
-        "3: {} {} {}ms",
-        most_run_scripts[2].runcount,
-        most_run_scripts[2]
-            .name
-            .truecolor(colors[2].r, colors[2].g, colors[2].b),
-        most_run_scripts[2]
-            .avgruntime_ms
-            .unwrap_or(NotNan::new(-1.0).unwrap())
-    );
+/// Seed for the score draws, so the same config always plots the same.
+const SCORE_SEED: u64 = 0;
+
+/// Plots zoom in on the range between these quantiles of the plotted scripts' posteriors, so
+/// failure rates around 1e-4 don't end up squashed against the y axis.
+const ZOOM_QUANTILES: (f64, f64) = (0.001, 0.999);
+
+/// Posterior mean adjusted for runtime and bias, to pick the scripts worth plotting.
+fn plot_score(script: &Script, prior: &Prior) -> NotNan<f64> {
+    let mean = PosteriorSummary::new(&script.results, prior).mean;
+    skew_percentile(
+        NotNan::new(mean).unwrap(),
+        &script
+            .mean_runtime_ms()
+            .and_then(|runtime| NotNan::new(runtime).ok()),
+        &script.bias,
+    )
 }
 
-pub fn plot_top_3_inverses(scripts: &[Script]) {
-    if scripts.len() < 3 {
-        println!("Cannot plot the top 3 inverses with less than 3 scripts.");
+/// Plot a curve for each of the `top` scripts chosen by `selection`, followed by a legend.
+/// `costs` are the cost models of `scripts`, used to sample the scores of [`Curve::Skewed`].
+pub fn plot_top(
+    scripts: &[Script],
+    priors: &[Prior],
+    costs: &[Option<CostModel>],
+    top: usize,
+    selection: &PlotSelection,
+    curve: Curve,
+) {
+    let chosen_ids = choose_plotted_ids(scripts, priors, top, selection);
+    if chosen_ids.is_empty() {
+        println!("No scripts to plot.");
         return;
     }
-
-    let mut scripts = scripts.to_owned();
-
-    scripts.sort_by(|a, b| b.runcount.partial_cmp(&a.runcount).unwrap());
-
-    let most_run_scripts: Vec<&Script> = scripts.iter().take(3).collect();
+    let chosen = chosen_ids
+        .iter()
+        .map(|&index| (&scripts[index], &priors[index]))
+        .collect::<Vec<_>>();
 
     use textplots::{Chart, Shape};
 
+    let (description, x_label, y_label) = match curve {
+        Curve::Cdf => (
+            "Cumulative probability of each failure rate",
+            "failure rate",
+            "cumulative probability",
+        ),
+        Curve::Pdf => (
+            "Posterior density of each failure rate",
+            "failure rate",
+            "density",
+        ),
+        Curve::Skewed => (
+            "Density of each score, the failure rate skewed by sampled cost and bias, estimated from sampled scores",
+            "score",
+            "density",
+        ),
+    };
     println!(
-        "Plot of inverse 3 run scripts. Minimizing time per interesting case (area under curve)."
+        "Plot of top {} scripts by {}. {}.",
+        chosen.len(),
+        selection,
+        description
     );
 
-    let colors = [
-        RGB8 {
-            r: 100,
-            g: 250,
-            b: 200,
-        },
-        RGB8 {
-            r: 200,
-            g: 250,
-            b: 100,
-        },
-        RGB8 {
-            r: 200,
-            g: 100,
-            b: 250,
+    let ((low, high), lines) = match curve {
+        Curve::Cdf | Curve::Pdf => {
+            let posteriors = chosen
+                .iter()
+                .map(|&(script, prior)| {
+                    (
+                        script.results.interesting as f64 + prior.alpha,
+                        script.results.uninteresting as f64 + prior.beta,
+                        1.0,
+                    )
+                })
+                .collect::<Vec<_>>();
+            let (low, high) = zoom_range(&posteriors);
+            let lines = posteriors
+                .iter()
+                .map(|&(alpha, beta, _)| {
+                    curve_points(low, high, |x| match curve {
+                        Curve::Cdf => unsafe { boost_ibeta(alpha, beta, x) },
+                        _ => beta_density(alpha, beta, x),
+                    })
+                })
+                .collect::<Vec<_>>();
+            ((low, high), lines)
+        }
+        Curve::Skewed => {
+            let mut rng = StdRng::seed_from_u64(SCORE_SEED);
+            let scores = chosen_ids
+                .iter()
+                .map(|&index| {
+                    sample_scores(&scripts[index], &priors[index], &costs[index], &mut rng)
+                })
+                .collect::<Vec<_>>();
+            let (low, high) = score_range(&scores);
+            let lines = scores
+                .iter()
+                .map(|scores| score_histogram(scores, low, high))
+                .collect::<Vec<_>>();
+            ((low, high), lines)
+        }
+    };
+
+    let colors = palette(chosen.len());
+    let shapes = lines
+        .iter()
+        .map(|points| Shape::Lines(points))
+        .collect::<Vec<_>>();
+
+    println!("y: {}", y_label);
+    // Drawn last to first, so the first script's curve ends up on top.
+    let mut chart = Chart::new(120, 60, low as f32, high as f32);
+    shapes
+        .iter()
+        .zip(&colors)
+        .rev()
+        .fold(&mut chart, |chart, (shape, color)| {
+            chart.linecolorplot(shape, *color)
+        })
+        .display();
+    println!("x: {} from {:.3e} to {:.3e}", x_label, low, high);
+
+    print_legend(&chosen, &colors, selection);
+}
+
+/// `f` at evenly spaced points from `low` to `high`. Points where `f` isn't finite, like the
+/// density at 0 of a posterior with alpha below 1, are left out.
+fn curve_points(low: f64, high: f64, f: impl Fn(f64) -> f64) -> Vec<(f32, f32)> {
+    (0..=PLOT_POINTS)
+        .map(|i| low + (high - low) * i as f64 / PLOT_POINTS as f64)
+        .map(|x| (x as f32, f(x) as f32))
+        .filter(|(_, y)| y.is_finite())
+        .collect()
+}
+
+/// Scores drawn the way scripts are ranked: a failure rate from the posterior skewed by a cost
+/// sampled from the script's cost model and by its bias.
+fn sample_scores(
+    script: &Script,
+    prior: &Prior,
+    cost: &Option<CostModel>,
+    rng: &mut StdRng,
+) -> Vec<f64> {
+    let costs = [cost.clone()];
+    let mut scores = (0..SCORE_SAMPLES)
+        .flat_map(|_| {
+            thompson_scores_bias_runtime(
+                &[&script.results],
+                &[*prior],
+                &costs,
+                &[&script.bias],
+                rng,
+            )
+        })
+        .map(f64::from)
+        .collect::<Vec<_>>();
+    scores.sort_by(f64::total_cmp);
+    scores
+}
+
+/// The range between [`ZOOM_QUANTILES`] of the sorted scores of every plotted script.
+fn score_range(scores: &[Vec<f64>]) -> (f64, f64) {
+    let quantile = |scores: &[f64], q: f64| scores[((scores.len() - 1) as f64 * q) as usize];
+    let (low, high) = scores.iter().filter(|scores| !scores.is_empty()).fold(
+        (f64::INFINITY, f64::NEG_INFINITY),
+        |(low, high), scores| {
+            (
+                low.min(quantile(scores, ZOOM_QUANTILES.0)),
+                high.max(quantile(scores, ZOOM_QUANTILES.1)),
+            )
         },
-    ];
+    );
+    if low > high {
+        return (0.0, 1.0);
+    }
+    (low, high.max(low + f64::EPSILON))
+}
 
-    Chart::new(120, 60, 0.0, 1.0)
-        .linecolorplot(
-            &Shape::Continuous(Box::new(|x| unsafe {
-                f32::from(
-                    skew_percentile(
-                        NotNan::new(boost_ibeta_inv(
-                            (most_run_scripts[2].results.interesting + 1) as f64,
-                            (most_run_scripts[2].results.uninteresting + 1) as f64,
-                            x.into(),
-                        ))
-                        .unwrap(),
-                        &most_run_scripts[2].avgruntime_ms,
-                        &most_run_scripts[2].bias,
-                    )
-                    .as_f32(),
-                )
-            })),
-            colors[2],
-        )
-        .linecolorplot(
-            &Shape::Continuous(Box::new(|x| unsafe {
-                f32::from(
-                    skew_percentile(
-                        NotNan::new(boost_ibeta_inv(
-                            (most_run_scripts[1].results.interesting + 1) as f64,
-                            (most_run_scripts[1].results.uninteresting + 1) as f64,
-                            x.into(),
-                        ))
-                        .unwrap(),
-                        &most_run_scripts[1].avgruntime_ms,
-                        &most_run_scripts[1].bias,
-                    )
-                    .as_f32(),
-                )
-            })),
-            colors[1],
+/// Density of the scores between `low` and `high`, from a histogram with [`SCORE_BINS`] bins.
+fn score_histogram(scores: &[f64], low: f64, high: f64) -> Vec<(f32, f32)> {
+    let width = (high - low) / SCORE_BINS as f64;
+    let mut counts = [0usize; SCORE_BINS];
+    for &score in scores {
+        if (low..=high).contains(&score) {
+            counts[(((score - low) / width) as usize).min(SCORE_BINS - 1)] += 1;
+        }
+    }
+
+    counts
+        .iter()
+        .enumerate()
+        .map(|(bin, &count)| {
+            let centre = low + width * (bin as f64 + 0.5);
+            let density = count as f64 / (scores.len().max(1) as f64 * width);
+            (centre as f32, density as f32)
+        })
+        .filter(|(_, density)| density.is_finite())
+        .collect()
+}
+
+/// The `top` scripts chosen by `selection`, best first.
+pub fn choose_plotted<'a>(
+    scripts: &'a [Script],
+    priors: &'a [Prior],
+    top: usize,
+    selection: &PlotSelection,
+) -> Vec<(&'a Script, &'a Prior)> {
+    choose_plotted_ids(scripts, priors, top, selection)
+        .into_iter()
+        .map(|index| (&scripts[index], &priors[index]))
+        .collect()
+}
+
+/// Indices of the `top` scripts chosen by `selection`, best first.
+fn choose_plotted_ids(
+    scripts: &[Script],
+    priors: &[Prior],
+    top: usize,
+    selection: &PlotSelection,
+) -> Vec<usize> {
+    let mut chosen = scripts
+        .iter()
+        .zip(priors)
+        .enumerate()
+        .filter(|(_, (script, _))| match selection {
+            PlotSelection::Name(pattern) => script.name.contains(pattern.as_str()),
+            _ => true,
+        })
+        .collect::<Vec<_>>();
+    match selection {
+        PlotSelection::Score => {
+            chosen.sort_by_key(|&(_, (script, prior))| std::cmp::Reverse(plot_score(script, prior)))
+        }
+        _ => chosen.sort_by_key(|(_, (script, _))| std::cmp::Reverse(script.runcount)),
+    }
+    chosen.truncate(top);
+    chosen.into_iter().map(|(index, _)| index).collect()
+}
+
+fn print_legend(chosen: &[(&Script, &Prior)], colors: &[RGB8], selection: &PlotSelection) {
+    println!("Top {} scripts by {}:", chosen.len(), selection);
+    for (i, ((script, _), color)) in chosen.iter().zip(colors).enumerate() {
+        println!(
+            "{}: {} {} {}ms",
+            i + 1,
+            script.runcount,
+            script.name.truecolor(color.r, color.g, color.b),
+            script.mean_runtime_ms().unwrap_or(-1.0)
+        );
+    }
+}
+
+/// Plot how the `top` scripts chosen by `selection` progressed over the run history: the
+/// interesting results they found, their share of the runs and their posterior medians.
+/// `dropped` runs were dropped from the start of the history. With `wall_clock` the x axis is
+/// the time since the first run in the history instead of the run count.
+pub fn plot_history(
+    scripts: &[Script],
+    priors: &[Prior],
+    history: &[Run],
+    dropped: u64,
+    top: usize,
+    selection: &PlotSelection,
+    wall_clock: bool,
+) {
+    let chosen = choose_plotted(scripts, priors, top, selection);
+    if chosen.is_empty() {
+        println!("No scripts to plot.");
+        return;
+    }
+    if history.is_empty() {
+        println!("No run history to plot. Runs are recorded by `bts run` and `bts record`.");
+        return;
+    }
+
+    let trajectories = trajectories(history, dropped, &chosen, HISTORY_SAMPLES);
+    let colors = palette(chosen.len());
+    let (x_label, xs) = if wall_clock {
+        let first_ms = history
+            .iter()
+            .map(|run| run.finished_at_ms)
+            .min()
+            .unwrap_or(0);
+        (
+            "seconds since the first run",
+            trajectories
+                .finished_at_ms
+                .iter()
+                .map(|&finished_at_ms| finished_at_ms.saturating_sub(first_ms) as f32 / 1000.0)
+                .collect::<Vec<_>>(),
         )
-        .linecolorplot(
-            &Shape::Continuous(Box::new(|x| unsafe {
-                f32::from(
-                    skew_percentile(
-                        NotNan::new(boost_ibeta_inv(
-                            (most_run_scripts[0].results.interesting + 1) as f64,
-                            (most_run_scripts[0].results.uninteresting + 1) as f64,
-                            x.into(),
-                        ))
-                        .unwrap(),
-                        &most_run_scripts[0].avgruntime_ms,
-                        &most_run_scripts[0].bias,
-                    )
-                    .as_f32(),
-                )
-            })),
-            colors[0],
+    } else {
+        (
+            "runs",
+            trajectories
+                .runs
+                .iter()
+                .map(|&runs| runs as f32)
+                .collect::<Vec<_>>(),
         )
-        .display();
+    };
+    let series = |values: &[f64]| {
+        xs.iter()
+            .zip(values)
+            .map(|(&x, &value)| (x, value as f32))
+            .collect::<Vec<_>>()
+    };
 
-    println!("Top 3 run scripts:");
-    println!(
-        "1: {} {} {}ms",
-        most_run_scripts[0].runcount,
-        most_run_scripts[0]
-            .name
-            .truecolor(colors[0].r, colors[0].g, colors[0].b),
-        most_run_scripts[0]
-            .avgruntime_ms
-            .unwrap_or(NotNan::new(-1.0).unwrap())
-    );
-    println!(
-        "2: {} {} {}ms",
-        most_run_scripts[1].runcount,
-        most_run_scripts[1]
-            .name
-            .truecolor(colors[1].r, colors[1].g, colors[1].b),
-        most_run_scripts[1]
-            .avgruntime_ms
-            .unwrap_or(NotNan::new(-1.0).unwrap())
-    );
-    println!(
-        "3: {} {} {}ms",
-        most_run_scripts[2].runcount,
-        most_run_scripts[2]
-            .name
-            .truecolor(colors[2].r, colors[2].g, colors[2].b),
-        most_run_scripts[2]
-            .avgruntime_ms
-            .unwrap_or(NotNan::new(-1.0).unwrap())
+    let plots = [
+        (
+            "Interesting results found so far",
+            "interesting results",
+            trajectories
+                .interesting
+                .iter()
+                .map(|counts| series(&counts.iter().map(|&n| n as f64).collect::<Vec<_>>()))
+                .collect::<Vec<_>>(),
+        ),
+        (
+            "Share of the runs so far",
+            "share of runs",
+            trajectories
+                .share
+                .iter()
+                .map(|shares| series(shares))
+                .collect(),
+        ),
+        (
+            "Posterior median failure rate",
+            "failure rate",
+            trajectories
+                .median
+                .iter()
+                .map(|medians| series(medians))
+                .collect(),
+        ),
+    ];
+
+    use textplots::{Chart, Shape};
+
+    let first_x = if wall_clock { 0.0 } else { dropped as f32 };
+    let last_x = xs.iter().copied().fold(first_x + 1.0, f32::max);
+    for (description, y_label, lines) in &plots {
+        println!(
+            "Plot of top {} scripts by {}. {}.",
+            chosen.len(),
+            selection,
+            description
+        );
+        println!("y: {}", y_label);
+        let shapes = lines
+            .iter()
+            .map(|points| Shape::Lines(points))
+            .collect::<Vec<_>>();
+        // Drawn last to first, so the first script's line ends up on top.
+        let mut chart = Chart::new(120, 60, first_x, last_x);
+        shapes
+            .iter()
+            .zip(&colors)
+            .rev()
+            .fold(&mut chart, |chart, (shape, color)| {
+                chart.linecolorplot(shape, *color)
+            })
+            .display();
+        println!("x: {} from {} to {}", x_label, first_x, last_x);
+    }
+
+    print_legend(&chosen, &colors, selection);
+}
+
+/// The range between [`ZOOM_QUANTILES`] of Beta(alpha, beta) posteriors stretched by a scale,
+/// given as `(alpha, beta, scale)`.
+pub fn zoom_range(curves: &[(f64, f64, f64)]) -> (f64, f64) {
+    let (low, high) = curves.iter().fold(
+        (f64::INFINITY, f64::NEG_INFINITY),
+        |(low, high), &(alpha, beta, scale)| unsafe {
+            (
+                low.min(scale * boost_ibeta_inv(alpha, beta, ZOOM_QUANTILES.0)),
+                high.max(scale * boost_ibeta_inv(alpha, beta, ZOOM_QUANTILES.1)),
+            )
+        },
     );
+    (low, high.max(low + f64::EPSILON))
+}
+
+/// Density of Beta(alpha, beta) at `rate`, which is 0 outside of [0, 1]. Infinite at 0 for
+/// alpha below 1, and at 1 for beta below 1.
+pub fn beta_density(alpha: f64, beta: f64, rate: f64) -> f64 {
+    if (0.0..=1.0).contains(&rate) {
+        unsafe { boost_ibeta_derivative(alpha, beta, rate) }
+    } else {
+        0.0
+    }
+}
+
+/// `count` colors with hues a golden angle apart, so any number of curves stay distinct.
+pub fn palette(count: usize) -> Vec<RGB8> {
+    (0..count)
+        .map(|i| hsv_to_rgb((160.0 + i as f64 * 137.508) % 360.0, 0.6, 0.98))
+        .collect()
+}
+
+fn hsv_to_rgb(hue: f64, saturation: f64, value: f64) -> RGB8 {
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+    let (r, g, b) = match (hue / 60.0) as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let channel = |c: f64| ((c + value - chroma) * 255.0).round() as u8;
+
+    RGB8 {
+        r: channel(r),
+        g: channel(g),
+        b: channel(b),
+    }
 }
 
 pub fn print_ranking_bias_runtime(
     scripts: &[Script],
-    runtimes: &[&Option<NotNan<f64>>],
-    user_biases: &[&NotNan<f64>],
+    priors: &[Prior],
+    costs: Vec<Option<CostModel>>,
     verbose: bool,
 ) {
-    let items = scripts
-        .iter()
-        .filter(|x| x.limit.is_none() || x.limit.unwrap() < x.results.interesting)
-        .map(|x| &x.results)
-        .collect::<Vec<_>>();
-    let entries: &[&ThompsonInfo] = items.as_slice();
-    let ranking = thompson_ranking_bias_runtime(entries, runtimes, user_biases);
+    let ranking = Candidates::active(scripts, priors, costs, |_, _| true).rank(false);
 
     if verbose {
         println!("Ranking (biased by runtime):");
 
         for (i, script) in ranking.iter().enumerate() {
-            println!("{}: {}", i + 1, scripts[*script].name,);
-            println!(
-                "- 50th percentile: {:.4}",
-                dist_area_at_percentile(&scripts[*script].results, 0.5)
-            );
-            println!("- Runs: {}", &scripts[*script].runcount);
+            println!("{}: {}", i + 1, scripts[script.0].name,);
+            print_posterior(&scripts[script.0], &priors[script.0]);
+            println!("- Runs: {}", &scripts[script.0].runcount);
             println!(
                 "- Observed percent {:.5}%",
-                scripts[*script].results.interesting as f64 / scripts[*script].runcount as f64
+                scripts[script.0].results.interesting as f64 / scripts[script.0].runcount as f64
                     * 100.
-            )
+            );
+            if let Some(runtime) = scripts[script.0].runtime_model() {
+                println!(
+                    "- Typical runtime: {:.2}ms (log std dev {:.2})",
+                    runtime.median_ms(),
+                    runtime.log_variance().sqrt()
+                );
+            }
+            if let Some(cpu_time) = scripts[script.0].cpu_time {
+                println!("- Typical CPU time: {:.2}ms", cpu_time.median_ms());
+            }
+            if let Some(max_rss_kb) = scripts[script.0].max_rss_kb {
+                println!("- Peak memory: {:.1}MB", max_rss_kb as f64 / 1024.0);
+            }
+            for (limit, breaches) in &scripts[script.0].limit_breaches {
+                println!("- Exceeded {} limit: {} times", limit, breaches);
+            }
+            if !scripts[script.0].outcomes.is_empty() {
+                let outcomes = scripts[script.0]
+                    .outcomes
+                    .iter()
+                    .map(|(termination, runs)| format!("{}: {}", termination, runs))
+                    .collect::<Vec<_>>();
+                println!("- Outcomes: {}", outcomes.join(", "));
+            }
+            if !scripts[script.0].signatures.is_empty() {
+                let signatures = scripts[script.0]
+                    .signatures
+                    .iter()
+                    .map(|(signature, runs)| format!("{}: {}", signature, runs))
+                    .collect::<Vec<_>>();
+                println!("- Signatures: {}", signatures.join(", "));
+            }
         }
     } else {
         ranking.iter().for_each(|script| {
-            println!("{}", scripts[*script].name);
+            println!("{}", scripts[script.0].name);
         });
     }
 }
 
-pub fn print_ranking(scripts: &[Script], verbose: bool) {
-    let items = scripts
+/// Print the posterior over the script's failure rate and how long finding the next
+/// interesting case is expected to take.
+fn print_posterior(script: &Script, prior: &Prior) {
+    let posterior = PosteriorSummary::new(&script.results, prior);
+    println!("- 50th percentile: {:.4}", posterior.median);
+    println!(
+        "- 5th and 95th percentiles: {:.4} to {:.4}",
+        posterior.p5, posterior.p95
+    );
+    println!("- Posterior mean: {:.4}", posterior.mean);
+
+    if let Some(runtime_ms) = script.mean_runtime_ms() {
+        let time = posterior.time_to_next(runtime_ms);
+        println!(
+            "- Expected time to next interesting case: {} (90% interval {} to {})",
+            format_duration_ms(time.expected_ms),
+            format_duration_ms(time.low_ms),
+            format_duration_ms(time.high_ms)
+        );
+    }
+}
+
+/// A duration in the largest unit that keeps it readable, e.g. `12.5min`.
+pub fn format_duration_ms(ms: f64) -> String {
+    const UNITS: [(f64, &str); 4] = [
+        (24.0 * 60.0 * 60.0 * 1000.0, " days"),
+        (60.0 * 60.0 * 1000.0, "h"),
+        (60.0 * 1000.0, "min"),
+        (1000.0, "s"),
+    ];
+
+    if !ms.is_finite() {
+        return "never".to_string();
+    }
+    UNITS
         .iter()
-        .filter(|x| x.limit.is_none() || x.limit.unwrap() < x.results.interesting)
-        .map(|x| &x.results)
+        .find(|(unit_ms, _)| ms >= *unit_ms)
+        .map(|(unit_ms, unit)| format!("{:.1}{}", ms / unit_ms, unit))
+        .unwrap_or_else(|| format!("{:.1}ms", ms))
+}
+
+/// Print a ranking by the probability of being best, with a 95% confidence interval for
+/// each probability.
+pub fn print_ranking_best_arm(
+    scripts: &[Script],
+    ranking: &[(ScriptId, BestArmEstimate)],
+    samples: usize,
+    verbose: bool,
+) {
+    if verbose {
+        println!("Ranking (probability of being best, {} samples):", samples);
+    }
+
+    for (i, (id, estimate)) in ranking.iter().enumerate() {
+        println!(
+            "{}: {} {:.2}% (95% CI {:.2}% to {:.2}%, mean score {:.4})",
+            i + 1,
+            scripts[id.0].name,
+            estimate.probability * 100.0,
+            estimate.low * 100.0,
+            estimate.high * 100.0,
+            estimate.mean_score
+        );
+    }
+}
+
+/// Print the scripts that stopped running and why.
+pub fn print_stopped(scripts: &[Script], priors: &[Prior]) {
+    let stopped = scripts
+        .iter()
+        .zip(priors)
+        .filter_map(|(script, prior)| Some((script, script.stop_reason(prior)?)))
         .collect::<Vec<_>>();
-    let entries: &[&ThompsonInfo] = items.as_slice();
-    let ranking = thompson_ranking(entries);
+    if stopped.is_empty() {
+        return;
+    }
+
+    println!("Stopped scripts:");
+    for (script, reason) in stopped {
+        println!("- {}: {}", script.name, reason);
+    }
+}
+
+/// Print the signature database, most frequent failures first.
+pub fn print_signatures(signatures: &BTreeMap<String, SignatureRecord>) {
+    if signatures.is_empty() {
+        return;
+    }
+
+    let mut signatures = signatures.iter().collect::<Vec<_>>();
+    signatures.sort_by_key(|(_, record)| std::cmp::Reverse(record.count));
+
+    println!("Failure signatures:");
+    for (signature, record) in signatures {
+        println!(
+            "- {}: {} runs, first found by {}",
+            signature, record.count, record.first_seen_by
+        );
+    }
+}
+
+pub fn print_ranking(scripts: &[Script], priors: &[Prior], verbose: bool) {
+    let ranking =
+        Candidates::active(scripts, priors, vec![None; scripts.len()], |_, _| true).rank(true);
 
     if verbose {
         println!("Ranking (raw):");
 
         for (i, script) in ranking.iter().enumerate() {
-            println!("{}: {}", i + 1, scripts[*script].name,);
-            println!(
-                "- 50th percentile: {:.4}",
-                dist_area_at_percentile(&scripts[*script].results, 0.5)
-            );
-            println!("- Runs: {}", &scripts[*script].runcount);
+            println!("{}: {}", i + 1, scripts[script.0].name,);
+            print_posterior(&scripts[script.0], &priors[script.0]);
+            println!("- Runs: {}", &scripts[script.0].runcount);
         }
     } else {
         ranking.iter().for_each(|script| {
-            println!("{}", scripts[*script].name);
+            println!("{}", scripts[script.0].name);
         });
     }
 }
+
+#[test]
+fn test_format_duration_ms() {
+    assert_eq!(format_duration_ms(12.34), "12.3ms");
+    assert_eq!(format_duration_ms(1500.0), "1.5s");
+    assert_eq!(format_duration_ms(90.0 * 60.0 * 1000.0), "1.5h");
+    assert_eq!(
+        format_duration_ms(3.0 * 24.0 * 60.0 * 60.0 * 1000.0),
+        "3.0 days"
+    );
+    assert_eq!(format_duration_ms(f64::INFINITY), "never");
+}
+
+#[test]
+fn test_plot_selection_from_str() {
+    assert_eq!("runs".parse(), Ok(PlotSelection::Runs));
+    assert_eq!("score".parse(), Ok(PlotSelection::Score));
+    assert_eq!(
+        "name=fuzz".parse(),
+        Ok(PlotSelection::Name("fuzz".to_string()))
+    );
+    assert!("fuzz".parse::<PlotSelection>().is_err());
+}
+
+#[test]
+fn test_palette_colors_are_distinct() {
+    let colors = palette(12);
+    for (i, a) in colors.iter().enumerate() {
+        for b in &colors[i + 1..] {
+            assert_ne!(a, b);
+        }
+    }
+}
+
+#[test]
+fn test_curve_from_str() {
+    assert_eq!("cdf".parse(), Ok(Curve::Cdf));
+    assert_eq!("pdf".parse(), Ok(Curve::Pdf));
+    assert_eq!("skewed".parse(), Ok(Curve::Skewed));
+    assert!("posterior".parse::<Curve>().is_err());
+}
+
+#[test]
+fn test_beta_density() {
+    // Beta(2, 2) has density 6x(1 - x).
+    assert!((beta_density(2.0, 2.0, 0.5) - 1.5).abs() < 1e-9);
+    assert_eq!(beta_density(2.0, 2.0, 1.5), 0.0);
+    assert_eq!(beta_density(2.0, 2.0, -0.5), 0.0);
+    // Boost overflows here. The wrapper returns infinity instead of unwinding into Rust.
+    assert_eq!(beta_density(0.5, 20.0, 0.0), f64::INFINITY);
+    assert_eq!(beta_density(2.0, 0.5, 1.0), f64::INFINITY);
+}
+
+#[test]
+fn test_curve_points_skip_infinite_densities() {
+    let points = curve_points(0.0, 1.0, |x| beta_density(0.5, 20.0, x));
+
+    assert_eq!(points.len(), PLOT_POINTS);
+    assert!(points.iter().all(|(x, y)| *x > 0.0 && y.is_finite()));
+}
+
+#[test]
+fn test_score_histogram_is_a_density() {
+    let mut script = Script::new("a".to_string(), "ls".to_string());
+    script.results.interesting = 10;
+    script.results.uninteresting = 90;
+    let cost = Some(CostModel::from(crate::runtime::RuntimeModel::from_mean(
+        100.0,
+    )));
+    let scores = sample_scores(
+        &script,
+        &Prior::default(),
+        &cost,
+        &mut StdRng::seed_from_u64(SCORE_SEED),
+    );
+
+    let (low, high) = score_range(std::slice::from_ref(&scores));
+    let histogram = score_histogram(&scores, low, high);
+    let width = (high - low) / SCORE_BINS as f64;
+    let area = histogram
+        .iter()
+        .map(|(_, density)| f64::from(*density) * width)
+        .sum::<f64>();
+    // The tails outside the zoomed range are left out.
+    assert!(area > 0.99 && area <= 1.0 + 1e-6, "{}", area);
+}
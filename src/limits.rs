@@ -0,0 +1,188 @@
+use std::{io, os::unix::process::ExitStatusExt, process::ExitStatus};
+
+use serde::{Deserialize, Serialize};
+
+/// Share of the address space limit a failed run's peak resident memory must reach for the
+/// failure to count as running out of memory. The address space also holds the program, its
+/// libraries and memory it reserved but never touched, so resident memory stays well below
+/// the limit when an allocation fails.
+const ADDRESS_SPACE_BREACH_SHARE: f64 = 0.5;
+
+/// Resource limits set with `setrlimit` on a script's process before it runs.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct ResourceLimits {
+    /// Maximum virtual memory in MB.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address_space_mb: Option<u64>,
+    /// Maximum CPU time in seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_seconds: Option<u64>,
+    /// Maximum number of open file descriptors.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub open_files: Option<u64>,
+    /// Whether the script may write core dumps. Left as inherited from bts when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub core_dumps: Option<bool>,
+}
+
+impl ResourceLimits {
+    pub fn is_empty(&self) -> bool {
+        *self == ResourceLimits::default()
+    }
+
+    /// These limits, falling back to `parent` for anything they don't set.
+    pub fn inherit(&self, parent: &ResourceLimits) -> ResourceLimits {
+        ResourceLimits {
+            address_space_mb: self.address_space_mb.or(parent.address_space_mb),
+            cpu_seconds: self.cpu_seconds.or(parent.cpu_seconds),
+            open_files: self.open_files.or(parent.open_files),
+            core_dumps: self.core_dumps.or(parent.core_dumps),
+        }
+    }
+
+    /// Set the limits on the current process. Called in the forked child before it execs the
+    /// script, so it sticks to async-signal-safe calls.
+    pub fn apply(&self) -> io::Result<()> {
+        let set = |resource, soft: libc::rlim_t, hard: libc::rlim_t| {
+            let limit = libc::rlimit {
+                rlim_cur: soft,
+                rlim_max: hard,
+            };
+            // SAFETY: setrlimit only reads the limit it is given.
+            match unsafe { libc::setrlimit(resource, &limit) } {
+                0 => Ok(()),
+                _ => Err(io::Error::last_os_error()),
+            }
+        };
+
+        if let Some(address_space_mb) = self.address_space_mb {
+            let bytes = address_space_mb.saturating_mul(1024 * 1024) as libc::rlim_t;
+            set(libc::RLIMIT_AS, bytes, bytes)?;
+        }
+        if let Some(cpu_seconds) = self.cpu_seconds {
+            // The soft limit sends SIGXCPU, so a breach can be told apart from other kills.
+            // The hard limit a second later kills scripts that ignore it.
+            set(
+                libc::RLIMIT_CPU,
+                cpu_seconds as libc::rlim_t,
+                cpu_seconds.saturating_add(1) as libc::rlim_t,
+            )?;
+        }
+        if let Some(open_files) = self.open_files {
+            let open_files = open_files as libc::rlim_t;
+            set(libc::RLIMIT_NOFILE, open_files, open_files)?;
+        }
+        match self.core_dumps {
+            Some(false) => set(libc::RLIMIT_CORE, 0, 0)?,
+            Some(true) => {
+                let mut current = libc::rlimit {
+                    rlim_cur: 0,
+                    rlim_max: 0,
+                };
+                // SAFETY: getrlimit only writes to the limit it is given.
+                if unsafe { libc::getrlimit(libc::RLIMIT_CORE, &mut current) } != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                set(libc::RLIMIT_CORE, current.rlim_max, current.rlim_max)?;
+            }
+            None => {}
+        }
+
+        Ok(())
+    }
+
+    /// The limit a failed run ran into, if any.
+    ///
+    /// Breaches are only recognised from how the run ended and what it used, never from what
+    /// it printed, so a genuine crash isn't mistaken for one. CPU time breaches are recognised
+    /// by the signal the kernel sends. Running out of address space sends no signal: the
+    /// allocation fails, and the script aborts, crashes or exits with an error. So any failed
+    /// run whose peak resident memory reached [`ADDRESS_SPACE_BREACH_SHARE`] of the limit ran
+    /// out of memory. A single huge allocation failing early can't be told apart from other
+    /// failures, nor can running out of file descriptors.
+    pub fn breached(
+        &self,
+        status: &ExitStatus,
+        cpu_ms: f64,
+        max_rss_kb: u64,
+    ) -> Option<&'static str> {
+        if status.success() {
+            return None;
+        }
+
+        if let (Some(cpu_seconds), Some(signal)) = (self.cpu_seconds, status.signal()) {
+            if signal == libc::SIGXCPU
+                || (signal == libc::SIGKILL && cpu_ms >= cpu_seconds as f64 * 1000.0)
+            {
+                return Some("cpu_seconds");
+            }
+        }
+        if self.address_space_mb.is_some_and(|address_space_mb| {
+            max_rss_kb as f64 >= address_space_mb as f64 * 1024.0 * ADDRESS_SPACE_BREACH_SHARE
+        }) {
+            return Some("address_space_mb");
+        }
+
+        None
+    }
+}
+
+#[test]
+fn test_breached() {
+    let limits = ResourceLimits {
+        address_space_mb: Some(100),
+        cpu_seconds: Some(1),
+        open_files: Some(16),
+        core_dumps: None,
+    };
+    let status = ExitStatus::from_raw;
+
+    assert_eq!(
+        limits.breached(&status(libc::SIGXCPU), 1000.0, 0),
+        Some("cpu_seconds")
+    );
+
+    // Killed before reaching the CPU limit, e.g. by a timeout.
+    assert_eq!(limits.breached(&status(libc::SIGKILL), 10.0, 0), None);
+
+    assert_eq!(
+        limits.breached(&status(libc::SIGABRT), 10.0, 60 * 1024),
+        Some("address_space_mb")
+    );
+    // Failed allocations often end in an error exit rather than a signal.
+    assert_eq!(
+        limits.breached(&status(2 << 8), 10.0, 60 * 1024),
+        Some("address_space_mb")
+    );
+
+    // A crash well within the memory limit is a finding, whatever it printed.
+    assert_eq!(limits.breached(&status(libc::SIGABRT), 10.0, 1024), None);
+    assert_eq!(limits.breached(&status(1 << 8), 10.0, 1024), None);
+
+    // Successful runs are never breaches.
+    assert_eq!(limits.breached(&status(0), 10.0, 100 * 1024), None);
+}
+
+#[test]
+fn test_limits_inherit() {
+    let parent = ResourceLimits {
+        address_space_mb: Some(1024),
+        core_dumps: Some(false),
+        ..Default::default()
+    };
+    let child = ResourceLimits {
+        address_space_mb: Some(64),
+        cpu_seconds: Some(10),
+        ..Default::default()
+    };
+
+    assert_eq!(
+        child.inherit(&parent),
+        ResourceLimits {
+            address_space_mb: Some(64),
+            cpu_seconds: Some(10),
+            open_files: None,
+            core_dumps: Some(false),
+        }
+    );
+}
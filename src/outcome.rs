@@ -0,0 +1,199 @@
+use std::{collections::BTreeMap, fmt, os::unix::process::ExitStatusExt, process::ExitStatus};
+
+use serde::{Deserialize, Serialize};
+
+/// How a run ended.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Termination {
+    Exit(i32),
+    Signal(i32),
+    /// Killed for running longer than its `timeout_ms`.
+    Timeout,
+}
+
+impl Termination {
+    pub fn from_status(status: &ExitStatus) -> Termination {
+        match (status.code(), status.signal()) {
+            (Some(code), _) => Termination::Exit(code),
+            (None, Some(signal)) => Termination::Signal(signal),
+            // Stopped or continued processes aren't reaped, so this doesn't happen.
+            (None, None) => Termination::Exit(-1),
+        }
+    }
+}
+
+impl fmt::Display for Termination {
+    /// The key used for the termination in `outcomes` and oracle rules, e.g. `exit 1` or
+    /// `SIGSEGV`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Termination::Exit(code) => write!(f, "exit {}", code),
+            Termination::Signal(signal) => match signal_name(*signal) {
+                Some(name) => write!(f, "{}", name),
+                None => write!(f, "signal {}", signal),
+            },
+            Termination::Timeout => write!(f, "timeout"),
+        }
+    }
+}
+
+fn signal_name(signal: i32) -> Option<&'static str> {
+    let name = match signal {
+        libc::SIGHUP => "SIGHUP",
+        libc::SIGINT => "SIGINT",
+        libc::SIGQUIT => "SIGQUIT",
+        libc::SIGILL => "SIGILL",
+        libc::SIGTRAP => "SIGTRAP",
+        libc::SIGABRT => "SIGABRT",
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGKILL => "SIGKILL",
+        libc::SIGUSR1 => "SIGUSR1",
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGUSR2 => "SIGUSR2",
+        libc::SIGPIPE => "SIGPIPE",
+        libc::SIGALRM => "SIGALRM",
+        libc::SIGTERM => "SIGTERM",
+        libc::SIGXCPU => "SIGXCPU",
+        libc::SIGXFSZ => "SIGXFSZ",
+        libc::SIGSYS => "SIGSYS",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// What a run counts as.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Interesting,
+    Uninteresting,
+    /// The run says nothing about the script, e.g. it was killed for running out of memory.
+    /// It counts as neither interesting nor uninteresting.
+    Error,
+}
+
+/// What a run that ended with `termination` counts as, according to the `oracle` rules.
+///
+/// Rules are keyed like the termination, e.g. `exit 2` or `SIGSEGV`, with `exit *` and
+/// `signal *` matching any exit code or signal. `signal N` matches signal N by number, even
+/// if it has a name. Without a matching rule, exiting with 0 is
+/// uninteresting, timing out is an error and anything else is interesting.
+pub fn judge(oracle: &BTreeMap<String, Verdict>, termination: Termination) -> Verdict {
+    let fallback = match termination {
+        Termination::Exit(_) => Some("exit *"),
+        Termination::Signal(_) => Some("signal *"),
+        Termination::Timeout => None,
+    };
+
+    let by_number = match termination {
+        Termination::Signal(signal) => Some(format!("signal {}", signal)),
+        _ => None,
+    };
+
+    oracle
+        .get(&termination.to_string())
+        .or_else(|| by_number.and_then(|by_number| oracle.get(&by_number)))
+        .or_else(|| fallback.and_then(|fallback| oracle.get(fallback)))
+        .copied()
+        .unwrap_or(match termination {
+            Termination::Exit(0) => Verdict::Uninteresting,
+            Termination::Timeout => Verdict::Error,
+            _ => Verdict::Interesting,
+        })
+}
+
+/// Whether an oracle rule can match any termination.
+pub fn is_valid_rule(rule: &str) -> bool {
+    match rule.split_once(' ') {
+        Some(("exit" | "signal", "*")) => true,
+        Some(("exit" | "signal", number)) => number.parse::<i32>().is_ok(),
+        Some(_) => false,
+        None if rule == "timeout" => true,
+        None => (1..=64).any(|signal| signal_name(signal) == Some(rule)),
+    }
+}
+
+#[test]
+fn test_termination_keys() {
+    assert_eq!(
+        Termination::from_status(&ExitStatus::from_raw(3 << 8)).to_string(),
+        "exit 3"
+    );
+    assert_eq!(
+        Termination::from_status(&ExitStatus::from_raw(libc::SIGSEGV)).to_string(),
+        "SIGSEGV"
+    );
+    assert_eq!(Termination::Signal(64).to_string(), "signal 64");
+}
+
+#[test]
+fn test_judge() {
+    let oracle = BTreeMap::from([
+        ("SIGKILL".to_string(), Verdict::Error),
+        ("signal *".to_string(), Verdict::Interesting),
+        ("exit *".to_string(), Verdict::Uninteresting),
+        ("exit 139".to_string(), Verdict::Interesting),
+    ]);
+
+    assert_eq!(
+        judge(&oracle, Termination::Signal(libc::SIGKILL)),
+        Verdict::Error
+    );
+    assert_eq!(
+        judge(&oracle, Termination::Signal(libc::SIGSEGV)),
+        Verdict::Interesting
+    );
+    assert_eq!(judge(&oracle, Termination::Exit(1)), Verdict::Uninteresting);
+    assert_eq!(judge(&oracle, Termination::Exit(139)), Verdict::Interesting);
+
+    let builtin = BTreeMap::new();
+    assert_eq!(
+        judge(&builtin, Termination::Exit(0)),
+        Verdict::Uninteresting
+    );
+    assert_eq!(judge(&builtin, Termination::Exit(1)), Verdict::Interesting);
+    assert_eq!(
+        judge(&builtin, Termination::Signal(libc::SIGKILL)),
+        Verdict::Interesting
+    );
+    assert_eq!(judge(&builtin, Termination::Timeout), Verdict::Error);
+
+    let by_number = BTreeMap::from([
+        ("signal 11".to_string(), Verdict::Uninteresting),
+        ("SIGKILL".to_string(), Verdict::Error),
+        ("signal 9".to_string(), Verdict::Interesting),
+    ]);
+    assert_eq!(
+        judge(&by_number, Termination::Signal(libc::SIGSEGV)),
+        Verdict::Uninteresting
+    );
+    // The name takes precedence over the number.
+    assert_eq!(
+        judge(&by_number, Termination::Signal(libc::SIGKILL)),
+        Verdict::Error
+    );
+
+    let timeouts = BTreeMap::from([
+        ("signal *".to_string(), Verdict::Uninteresting),
+        ("timeout".to_string(), Verdict::Interesting),
+    ]);
+    assert_eq!(judge(&timeouts, Termination::Timeout), Verdict::Interesting);
+}
+
+#[test]
+fn test_is_valid_rule() {
+    for rule in [
+        "exit 2",
+        "exit *",
+        "signal *",
+        "signal 64",
+        "SIGSEGV",
+        "timeout",
+    ] {
+        assert!(is_valid_rule(rule), "{}", rule);
+    }
+    for rule in ["exit", "exit two", "SEGV", "sigsegv", "crash *"] {
+        assert!(!is_valid_rule(rule), "{}", rule);
+    }
+}
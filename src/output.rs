@@ -0,0 +1,340 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Script,
+    prior::Prior,
+    runtime::CostModel,
+    selection::{BestArmEstimate, Candidates, ScriptId},
+    thompson::{PosteriorSummary, TimeToNext},
+};
+
+/// How `rank` and `summarize` print their results.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Free-form text for people.
+    #[default]
+    Text,
+    Json,
+    Csv,
+    Tsv,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            "tsv" => Ok(OutputFormat::Tsv),
+            _ => Err(format!("Unknown format {}. Use text, json, csv or tsv.", s)),
+        }
+    }
+}
+
+/// One script, as printed in the machine-readable formats.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct RankingRow {
+    pub name: String,
+    /// Position in the ranking, starting at 1. Empty for scripts that have stopped.
+    pub rank: Option<usize>,
+    /// Mean of the failure rate posterior.
+    pub posterior_mean: f64,
+    pub posterior_median: f64,
+    /// Bounds of the 90% credible interval of the failure rate.
+    pub credible_low: f64,
+    pub credible_high: f64,
+    pub runs: u64,
+    pub interesting: u64,
+    pub avg_runtime_ms: Option<f64>,
+    pub bias: f64,
+    /// The sample the script was ranked by, or the mean sample when ranking by the
+    /// probability of being best. Empty for scripts that have stopped.
+    pub score: Option<f64>,
+    /// Estimated probability of being the best script, when ranking by it.
+    pub probability_best: Option<f64>,
+    /// Expected wall-clock time to the next interesting case, and its 90% credible interval.
+    /// Empty for scripts without a runtime, and for times that are infinite.
+    pub expected_time_to_next_ms: Option<f64>,
+    pub time_to_next_low_ms: Option<f64>,
+    pub time_to_next_high_ms: Option<f64>,
+    /// "Active", or why the script stopped.
+    pub status: String,
+}
+
+const COLUMNS: [&str; 16] = [
+    "name",
+    "rank",
+    "posterior_mean",
+    "posterior_median",
+    "credible_low",
+    "credible_high",
+    "runs",
+    "interesting",
+    "avg_runtime_ms",
+    "bias",
+    "score",
+    "probability_best",
+    "expected_time_to_next_ms",
+    "time_to_next_low_ms",
+    "time_to_next_high_ms",
+    "status",
+];
+
+impl RankingRow {
+    fn new(rank: Option<usize>, script: &Script, prior: &Prior, score: Option<f64>) -> RankingRow {
+        let posterior = PosteriorSummary::new(&script.results, prior);
+        let runtime_ms = script.mean_runtime_ms();
+        let time_to_next = runtime_ms.map(|runtime_ms| posterior.time_to_next(runtime_ms));
+        let finite_time = |time: fn(&TimeToNext) -> f64| {
+            time_to_next.as_ref().map(time).filter(|ms| ms.is_finite())
+        };
+
+        RankingRow {
+            name: script.name.clone(),
+            rank,
+            posterior_mean: posterior.mean,
+            posterior_median: posterior.median,
+            credible_low: posterior.p5,
+            credible_high: posterior.p95,
+            runs: script.runcount,
+            interesting: script.results.interesting,
+            avg_runtime_ms: runtime_ms,
+            bias: f64::from(script.bias),
+            score,
+            probability_best: None,
+            expected_time_to_next_ms: finite_time(|time| time.expected_ms),
+            time_to_next_low_ms: finite_time(|time| time.low_ms),
+            time_to_next_high_ms: finite_time(|time| time.high_ms),
+            status: script
+                .stop_reason(prior)
+                .unwrap_or_else(|| "Active".to_string()),
+        }
+    }
+
+    fn fields(&self) -> [String; 16] {
+        fn optional<T: ToString>(value: Option<T>) -> String {
+            value.map(|value| value.to_string()).unwrap_or_default()
+        }
+
+        [
+            self.name.clone(),
+            optional(self.rank),
+            self.posterior_mean.to_string(),
+            self.posterior_median.to_string(),
+            self.credible_low.to_string(),
+            self.credible_high.to_string(),
+            self.runs.to_string(),
+            self.interesting.to_string(),
+            optional(self.avg_runtime_ms),
+            self.bias.to_string(),
+            optional(self.score),
+            optional(self.probability_best),
+            optional(self.expected_time_to_next_ms),
+            optional(self.time_to_next_low_ms),
+            optional(self.time_to_next_high_ms),
+            self.status.clone(),
+        ]
+    }
+}
+
+/// A script chosen by `bts next`, with what it takes to run it elsewhere.
+#[derive(Debug, Serialize)]
+pub struct NextScript<'a> {
+    pub name: &'a str,
+    pub command: &'a str,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: &'a BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+}
+
+impl<'a> From<&'a Script> for NextScript<'a> {
+    fn from(script: &'a Script) -> Self {
+        NextScript {
+            name: &script.name,
+            command: &script.command,
+            env: &script.env,
+            cwd: script.cwd.as_deref(),
+            timeout_ms: script.timeout_ms,
+        }
+    }
+}
+
+/// Rank the active scripts from a single draw, like `print_ranking` and
+/// `print_ranking_bias_runtime` do.
+pub fn ranking_rows(
+    scripts: &[Script],
+    priors: &[Prior],
+    costs: Vec<Option<CostModel>>,
+    ignore_runtime: bool,
+) -> Vec<RankingRow> {
+    Candidates::active(scripts, priors, costs, |_, _| true)
+        .rank_with_scores(ignore_runtime)
+        .into_iter()
+        .enumerate()
+        .map(|(position, (id, score))| {
+            RankingRow::new(
+                Some(position + 1),
+                &scripts[id.0],
+                &priors[id.0],
+                Some(score),
+            )
+        })
+        .collect()
+}
+
+/// A row for every script: the ranking of the active scripts, then the scripts that have
+/// stopped, without a rank or score.
+pub fn summary_rows(
+    scripts: &[Script],
+    priors: &[Prior],
+    costs: Vec<Option<CostModel>>,
+    ignore_runtime: bool,
+) -> Vec<RankingRow> {
+    let mut rows = ranking_rows(scripts, priors, costs, ignore_runtime);
+    rows.extend(
+        scripts
+            .iter()
+            .zip(priors)
+            .filter(|(script, prior)| script.stop_reason(prior).is_some())
+            .map(|(script, prior)| RankingRow::new(None, script, prior, None)),
+    );
+    rows
+}
+
+/// Rows for a ranking by the probability of being best.
+pub fn best_arm_rows(
+    scripts: &[Script],
+    priors: &[Prior],
+    ranking: &[(ScriptId, BestArmEstimate)],
+) -> Vec<RankingRow> {
+    ranking
+        .iter()
+        .enumerate()
+        .map(|(position, (id, estimate))| RankingRow {
+            probability_best: Some(estimate.probability),
+            ..RankingRow::new(
+                Some(position + 1),
+                &scripts[id.0],
+                &priors[id.0],
+                Some(estimate.mean_score),
+            )
+        })
+        .collect()
+}
+
+/// Print the rows in a machine-readable format. Does nothing for [`OutputFormat::Text`],
+/// which each command prints its own way.
+pub fn print_rows(rows: &[RankingRow], format: OutputFormat) {
+    print!("{}", Rows(rows, format));
+}
+
+struct Rows<'a>(&'a [RankingRow], OutputFormat);
+
+impl fmt::Display for Rows<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Rows(rows, format) = *self;
+        let (separator, escape): (&str, fn(&str) -> String) = match format {
+            OutputFormat::Text => return Ok(()),
+            OutputFormat::Json => {
+                return writeln!(f, "{}", serde_json::to_string_pretty(rows).unwrap())
+            }
+            OutputFormat::Csv => (",", csv_field),
+            OutputFormat::Tsv => ("\t", tsv_field),
+        };
+
+        writeln!(f, "{}", COLUMNS.join(separator))?;
+        for row in rows {
+            let fields = row.fields().map(|field| escape(&field));
+            writeln!(f, "{}", fields.join(separator))?;
+        }
+        Ok(())
+    }
+}
+
+/// Quote fields containing commas, quotes or line breaks, doubling the quotes inside.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// TSV has no quoting, so tabs and line breaks become spaces.
+fn tsv_field(field: &str) -> String {
+    field.replace(['\t', '\n', '\r'], " ")
+}
+
+#[test]
+fn test_delimited_rows() {
+    let row = RankingRow {
+        name: "exit, then \"retry\"".to_string(),
+        rank: Some(1),
+        posterior_mean: 0.5,
+        posterior_median: 0.5,
+        credible_low: 0.025,
+        credible_high: 0.975,
+        runs: 0,
+        interesting: 0,
+        avg_runtime_ms: None,
+        bias: 1.0,
+        score: Some(0.25),
+        probability_best: None,
+        expected_time_to_next_ms: None,
+        time_to_next_low_ms: None,
+        time_to_next_high_ms: None,
+        status: "Active".to_string(),
+    };
+
+    assert_eq!(
+        Rows(std::slice::from_ref(&row), OutputFormat::Csv).to_string(),
+        "name,rank,posterior_mean,posterior_median,credible_low,credible_high,runs,interesting,avg_runtime_ms,bias,score,probability_best,\
+         expected_time_to_next_ms,time_to_next_low_ms,time_to_next_high_ms,status\n\
+         \"exit, then \"\"retry\"\"\",1,0.5,0.5,0.025,0.975,0,0,,1,0.25,,,,,Active\n"
+    );
+
+    let row = RankingRow {
+        name: "a\tb".to_string(),
+        ..row
+    };
+    assert_eq!(
+        Rows(&[row], OutputFormat::Tsv).to_string().lines().nth(1),
+        Some("a b\t1\t0.5\t0.5\t0.025\t0.975\t0\t0\t\t1\t0.25\t\t\t\t\tActive")
+    );
+}
+
+#[test]
+fn test_ranking_rows() {
+    let mut scripts = vec![
+        Script::new("a".to_string(), "ls".to_string()),
+        Script::new("b".to_string(), "ls".to_string()),
+    ];
+    scripts[1].limit = Some(0);
+    let priors = vec![Prior::default(); 2];
+    let b_status = scripts[1].stop_reason(&priors[1]).unwrap();
+
+    let rows = ranking_rows(&scripts, &priors, vec![None, None], true);
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].name, "a");
+    assert_eq!(rows[0].rank, Some(1));
+    assert_eq!(rows[0].status, "Active");
+    assert_eq!(rows[0].posterior_mean, 0.5);
+    assert!(rows[0].credible_low < rows[0].posterior_median);
+    assert!(rows[0].posterior_median < rows[0].credible_high);
+
+    // Summaries list the stopped scripts last, without a rank or score.
+    let rows = summary_rows(&scripts, &priors, vec![None, None], true);
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[1].name, "b");
+    assert_eq!(rows[1].rank, None);
+    assert_eq!(rows[1].score, None);
+    assert_eq!(rows[1].status, b_status);
+}
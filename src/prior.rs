@@ -0,0 +1,237 @@
+use std::collections::BTreeMap;
+
+use crate::{config::Script, thompson::ThompsonInfo};
+
+/// Beta prior pseudo-counts added to a script's results before sampling.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Prior {
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Default for Prior {
+    /// Beta(1, 1), uniform over the failure rate.
+    fn default() -> Self {
+        Prior {
+            alpha: 1.0,
+            beta: 1.0,
+        }
+    }
+}
+
+/// Totals over the observed failure rates of a group of scripts, from which the method of
+/// moments estimates their shared prior. Scripts can be left out again, so every script can
+/// get a prior learned from its siblings only.
+#[derive(Clone, Copy, Debug, Default)]
+struct GroupStats {
+    scripts: f64,
+    total_runs: f64,
+    total_interesting: f64,
+    sum_rate: f64,
+    sum_rate_squared: f64,
+    sum_inverse_runs: f64,
+}
+
+impl GroupStats {
+    fn new(entries: &[&ThompsonInfo]) -> GroupStats {
+        let mut stats = GroupStats::default();
+        for entry in entries {
+            stats.add(entry, 1.0);
+        }
+        stats
+    }
+
+    /// Add the entry with `sign` 1, or take it out again with -1. Entries that haven't run
+    /// say nothing about the failure rate and are skipped.
+    fn add(&mut self, entry: &ThompsonInfo, sign: f64) {
+        let runs = (entry.interesting + entry.uninteresting) as f64;
+        if runs == 0.0 {
+            return;
+        }
+
+        let rate = entry.interesting as f64 / runs;
+        self.scripts += sign;
+        self.total_runs += sign * runs;
+        self.total_interesting += sign * entry.interesting as f64;
+        self.sum_rate += sign * rate;
+        self.sum_rate_squared += sign * rate * rate;
+        self.sum_inverse_runs += sign / runs;
+    }
+
+    /// These totals without `entry`.
+    fn without(mut self, entry: &ThompsonInfo) -> GroupStats {
+        self.add(entry, -1.0);
+        self
+    }
+
+    /// Empirical Bayes estimate of the Beta prior shared by the group, using the method of
+    /// moments on the observed failure rates of the scripts that have run.
+    ///
+    /// Returns `None` with fewer than two scripts to learn from.
+    fn prior(&self) -> Option<Prior> {
+        if self.scripts < 2.0 {
+            return None;
+        }
+
+        // Smoothed so a group without any interesting results still gets a small positive rate.
+        let mean = (self.total_interesting + 1.0) / (self.total_runs + 2.0);
+        let variance = (self.sum_rate_squared - 2.0 * mean * self.sum_rate
+            + self.scripts * mean * mean)
+            .max(0.0)
+            / (self.scripts - 1.0);
+        // Part of the observed variance is binomial noise from the finite number of runs.
+        let sampling_variance = mean * (1.0 - mean) * self.sum_inverse_runs / self.scripts;
+        let between_scripts_variance = variance - sampling_variance;
+
+        // The prior is never worth more runs than the average sibling has, so a script's own
+        // results take over once it has run about as often as its siblings.
+        let max_strength = (self.total_runs / self.scripts).max(2.0);
+        let strength = if between_scripts_variance > 0.0 {
+            mean * (1.0 - mean) / between_scripts_variance - 1.0
+        } else {
+            max_strength
+        };
+        let strength = strength.clamp(2.0, max_strength);
+
+        Some(Prior {
+            alpha: mean * strength,
+            beta: (1.0 - mean) * strength,
+        })
+    }
+}
+
+/// The prior for every script. With `shared_prior`, each script's prior is learned from the
+/// other scripts in its group, leaving out its own results so they aren't counted twice.
+/// It falls back to the uniform prior while the siblings have too little data. Scripts
+/// without a group form one group.
+///
+/// A script's own `prior_alpha` and `prior_beta` take precedence over either.
+pub fn script_priors(scripts: &[Script], shared_prior: bool) -> Vec<Prior> {
+    if !shared_prior {
+        return scripts
+            .iter()
+            .map(|script| custom_prior(script, Prior::default()))
+            .collect();
+    }
+
+    let mut groups: BTreeMap<Option<&String>, Vec<&ThompsonInfo>> = BTreeMap::new();
+    for script in scripts {
+        groups
+            .entry(script.group.as_ref())
+            .or_default()
+            .push(&script.results);
+    }
+
+    let group_stats = groups
+        .into_iter()
+        .map(|(group, entries)| (group, GroupStats::new(&entries)))
+        .collect::<BTreeMap<_, _>>();
+
+    scripts
+        .iter()
+        .map(|script| {
+            let siblings = group_stats[&script.group.as_ref()].without(&script.results);
+            custom_prior(script, siblings.prior().unwrap_or_default())
+        })
+        .collect()
+}
+
+fn custom_prior(script: &Script, fallback: Prior) -> Prior {
+    Prior {
+        alpha: script.prior_alpha.unwrap_or(fallback.alpha),
+        beta: script.prior_beta.unwrap_or(fallback.beta),
+    }
+}
+
+#[test]
+fn test_group_prior_needs_two_scripts() {
+    let entry = ThompsonInfo {
+        interesting: 1,
+        uninteresting: 10,
+    };
+    let unrun = ThompsonInfo::default();
+
+    assert_eq!(GroupStats::new(&[&entry, &unrun]).prior(), None);
+}
+
+#[test]
+fn test_group_prior_rare_failures() {
+    let entries = [
+        ThompsonInfo {
+            interesting: 0,
+            uninteresting: 10000,
+        },
+        ThompsonInfo {
+            interesting: 1,
+            uninteresting: 9999,
+        },
+        ThompsonInfo {
+            interesting: 2,
+            uninteresting: 9998,
+        },
+    ];
+    let prior = GroupStats::new(&entries.iter().collect::<Vec<_>>())
+        .prior()
+        .unwrap();
+
+    // Far more pessimistic than Beta(1, 1) for a suite with failure rates around 1e-4.
+    let mean = prior.alpha / (prior.alpha + prior.beta);
+    assert!(mean > 0.5e-4 && mean < 2e-4, "{:?}", prior);
+    assert!(prior.alpha + prior.beta < 10000.5, "{:?}", prior);
+}
+
+#[test]
+fn test_script_priors_by_group() {
+    let mut scripts = vec![];
+    for (name, group, interesting) in [("a", "x", 5), ("b", "x", 7), ("c", "x", 2), ("d", "y", 1)] {
+        let mut script = Script::new(name.to_string(), "ls".to_string());
+        script.group = Some(group.to_string());
+        script.results = ThompsonInfo {
+            interesting,
+            uninteresting: 10,
+        };
+        scripts.push(script);
+    }
+
+    let close =
+        |a: Prior, b: Prior| (a.alpha - b.alpha).abs() < 1e-9 && (a.beta - b.beta).abs() < 1e-9;
+    let siblings_prior = |a: usize, b: usize| {
+        GroupStats::new(&[&scripts[a].results, &scripts[b].results])
+            .prior()
+            .unwrap()
+    };
+
+    let priors = script_priors(&scripts, true);
+    // Each script's prior comes from its siblings only. Taking a script back out of the group
+    // totals can differ from never adding it in the last bits.
+    assert!(close(priors[0], siblings_prior(1, 2)), "{:?}", priors);
+    assert!(close(priors[2], siblings_prior(0, 1)), "{:?}", priors);
+    assert!(!close(priors[0], priors[2]), "{:?}", priors);
+    // A group with a single script has nothing to learn from.
+    assert_eq!(priors[3], Prior::default());
+
+    assert_eq!(script_priors(&scripts, false), vec![Prior::default(); 4]);
+}
+
+#[test]
+fn test_script_priors_custom() {
+    let mut custom = Script::new("a".to_string(), "ls".to_string());
+    custom.prior_alpha = Some(0.5);
+    custom.prior_beta = Some(500.0);
+    let mut half_custom = Script::new("b".to_string(), "ls".to_string());
+    half_custom.prior_beta = Some(10.0);
+
+    assert_eq!(
+        script_priors(&[custom, half_custom], false),
+        vec![
+            Prior {
+                alpha: 0.5,
+                beta: 500.0
+            },
+            Prior {
+                alpha: 1.0,
+                beta: 10.0
+            }
+        ]
+    );
+}
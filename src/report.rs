@@ -0,0 +1,697 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    os::unix::ffi::OsStrExt,
+    path::{Component, Path, PathBuf},
+};
+
+use rgb::RGB8;
+
+use crate::{
+    config::Script,
+    history::{trajectories, Run},
+    insights::{
+        beta_density, choose_plotted, format_duration_ms, palette, zoom_range, PlotSelection,
+        HISTORY_SAMPLES,
+    },
+    prior::Prior,
+    selection::{BestArmEstimate, ScriptId},
+    signatures::SignatureRecord,
+    thompson::PosteriorSummary,
+};
+
+/// Posterior samples the report ranks scripts by, as with `rank --samples`.
+pub const REPORT_SAMPLES: usize = 10_000;
+
+/// Points along each posterior curve.
+const CURVE_POINTS: usize = 200;
+
+const CHART_WIDTH: f64 = 720.0;
+const CHART_HEIGHT: f64 = 320.0;
+/// Room for the axes and their labels: left, right, top and bottom.
+const CHART_MARGINS: (f64, f64, f64, f64) = (80.0, 20.0, 20.0, 50.0);
+const BAR_HEIGHT: f64 = 24.0;
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; margin-bottom: 1em; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; }
+th { background: #f0f0f0; cursor: pointer; user-select: none; }
+th[data-order=ascending]::after { content: ' \\25B2'; }
+th[data-order=descending]::after { content: ' \\25BC'; }
+td.number { text-align: right; font-variant-numeric: tabular-nums; }
+svg text { font-size: 12px; fill: #222; }
+ul.legend { list-style: none; padding: 0; }
+ul.legend span { display: inline-block; width: 1em; height: 1em; margin-right: 0.5em; vertical-align: middle; }
+";
+
+/// Sorts a table by the column whose header was clicked, by the cells' `data-sort` values
+/// when they have them and numerically when the values are numbers.
+const SORT_SCRIPT: &str = r#"
+document.querySelectorAll("table.sortable th").forEach(th => th.addEventListener("click", () => {
+    const column = th.cellIndex;
+    const body = th.closest("table").tBodies[0];
+    const ascending = th.dataset.order !== "ascending";
+    th.closest("tr").querySelectorAll("th").forEach(other => delete other.dataset.order);
+    th.dataset.order = ascending ? "ascending" : "descending";
+    const key = row => row.cells[column].dataset.sort ?? row.cells[column].textContent;
+    const rows = Array.from(body.rows).sort((a, b) => {
+        const [x, y] = [key(a), key(b)];
+        const [nx, ny] = [parseFloat(x), parseFloat(y)];
+        const order = isNaN(nx) || isNaN(ny) ? x.localeCompare(y) : nx - ny;
+        return ascending ? order : -order;
+    });
+    body.append(...rows);
+}));
+"#;
+
+/// A self-contained HTML report on a config: tables, plots and the run history, with no
+/// references to anything online.
+pub struct Report<'a> {
+    pub title: &'a str,
+    pub scripts: &'a [Script],
+    pub priors: &'a [Prior],
+    /// The active scripts by their probability of being best, as ranked by
+    /// [`Candidates::rank_by_best_arm`](crate::selection::Candidates::rank_by_best_arm).
+    pub ranking: &'a [(ScriptId, BestArmEstimate)],
+    pub history: &'a [Run],
+    /// Runs dropped from the start of `history`.
+    pub history_dropped: u64,
+    pub signatures: &'a BTreeMap<String, SignatureRecord>,
+    /// Directory with a subdirectory of artifacts for each script, named after the script.
+    pub artifacts: Option<&'a Path>,
+    /// Directory the report is written to. Artifact links are relative to it.
+    pub html_dir: &'a Path,
+    /// Scripts to plot, and how to pick them.
+    pub top: usize,
+    pub selection: &'a PlotSelection,
+}
+
+impl fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "<!DOCTYPE html>")?;
+        writeln!(f, "<html lang=\"en\">")?;
+        writeln!(f, "<head>")?;
+        writeln!(f, "<meta charset=\"utf-8\">")?;
+        writeln!(f, "<title>bts report: {}</title>", escape(self.title))?;
+        writeln!(f, "<style>{}</style>", STYLE)?;
+        writeln!(f, "</head>")?;
+        writeln!(f, "<body>")?;
+        writeln!(f, "<h1>bts report: {}</h1>", escape(self.title))?;
+
+        let runs = self
+            .scripts
+            .iter()
+            .map(|script| script.runcount)
+            .sum::<u64>();
+        let interesting = self
+            .scripts
+            .iter()
+            .map(|script| script.results.interesting)
+            .sum::<u64>();
+        writeln!(
+            f,
+            "<p>{} scripts, {} runs, {} interesting results, {} failure signatures.</p>",
+            self.scripts.len(),
+            runs,
+            interesting,
+            self.signatures.len()
+        )?;
+
+        self.write_scripts(f)?;
+
+        let chosen = choose_plotted(self.scripts, self.priors, self.top, self.selection);
+        let colors = palette(chosen.len());
+        writeln!(f, "<h2>Posteriors</h2>")?;
+        if chosen.is_empty() {
+            writeln!(f, "<p>No scripts to plot.</p>")?;
+        } else {
+            write!(f, "{}", posterior_chart(&chosen, &colors))?;
+            write!(f, "{}", runtime_chart(&chosen, &colors))?;
+            write_legend(f, &chosen, &colors)?;
+        }
+
+        writeln!(f, "<h2>Allocation history</h2>")?;
+        if self.history.is_empty() {
+            writeln!(f, "<p>No run history. Runs are recorded by <code>bts run</code> and <code>bts record</code>.</p>")?;
+        } else if !chosen.is_empty() {
+            let trajectories =
+                trajectories(self.history, self.history_dropped, &chosen, HISTORY_SAMPLES);
+            let runs = trajectories
+                .runs
+                .iter()
+                .map(|&runs| runs as f64)
+                .collect::<Vec<_>>();
+            let series = |values: Vec<Vec<f64>>| {
+                chosen
+                    .iter()
+                    .zip(&colors)
+                    .zip(values)
+                    .map(|((&(script, _), &color), values)| Series {
+                        name: &script.name,
+                        color,
+                        points: runs.iter().copied().zip(values).collect(),
+                    })
+                    .collect::<Vec<_>>()
+            };
+            let first_run = self.history_dropped as f64;
+            let last_run = runs.last().copied().unwrap_or(1.0).max(first_run + 1.0);
+
+            writeln!(f, "<h3>Share of the runs so far</h3>")?;
+            let share = LineChart {
+                x_label: "runs",
+                y_label: "share of runs",
+                x_range: (first_run, last_run),
+                series: series(trajectories.share),
+            };
+            write!(f, "{}", share)?;
+            writeln!(f, "<h3>Interesting results found so far</h3>")?;
+            let interesting = trajectories
+                .interesting
+                .into_iter()
+                .map(|counts| counts.into_iter().map(|count| count as f64).collect())
+                .collect();
+            let interesting = LineChart {
+                x_label: "runs",
+                y_label: "interesting results",
+                x_range: (first_run, last_run),
+                series: series(interesting),
+            };
+            write!(f, "{}", interesting)?;
+            write_legend(f, &chosen, &colors)?;
+        }
+
+        self.write_signatures(f)?;
+
+        writeln!(f, "<script>{}</script>", SORT_SCRIPT)?;
+        writeln!(f, "</body>")?;
+        writeln!(f, "</html>")
+    }
+}
+
+impl Report<'_> {
+    /// One row per script, the active ones in rank order followed by the stopped ones.
+    fn write_scripts(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "<h2>Scripts</h2>")?;
+        writeln!(f, "<table class=\"sortable\">")?;
+        writeln!(
+            f,
+            "<thead><tr><th>Rank</th><th>Script</th><th>P(best)</th><th>Runs</th>\
+             <th>Interesting</th><th>Posterior mean</th><th>Posterior median</th>\
+             <th>90% credible interval</th><th>Avg runtime</th><th>Bias</th><th>Status</th>\
+             <th>Artifacts</th></tr></thead>"
+        )?;
+        writeln!(f, "<tbody>")?;
+
+        let stopped = (0..self.scripts.len())
+            .map(ScriptId)
+            .filter(|id| self.ranking.iter().all(|(ranked, _)| ranked != id));
+        let rows = self
+            .ranking
+            .iter()
+            .enumerate()
+            .map(|(position, (id, estimate))| (*id, Some((position + 1, estimate))))
+            .chain(stopped.map(|id| (id, None)));
+        for (ScriptId(index), rank) in rows {
+            let script = &self.scripts[index];
+            let posterior = PosteriorSummary::new(&script.results, &self.priors[index]);
+
+            write!(f, "<tr>")?;
+            match rank {
+                Some((rank, estimate)) => write!(
+                    f,
+                    "<td class=\"number\">{}</td><td>{}</td><td class=\"number\" data-sort=\"{}\">{:.2}% ({:.2}% to {:.2}%)</td>",
+                    rank,
+                    escape(&script.name),
+                    estimate.probability,
+                    estimate.probability * 100.0,
+                    estimate.low * 100.0,
+                    estimate.high * 100.0
+                )?,
+                None => write!(f, "<td></td><td>{}</td><td></td>", escape(&script.name))?,
+            }
+            write!(
+                f,
+                "<td class=\"number\">{}</td><td class=\"number\">{}</td>\
+                 <td class=\"number\">{:.4}</td><td class=\"number\">{:.4}</td>\
+                 <td class=\"number\" data-sort=\"{}\">{:.4} to {:.4}</td>",
+                script.runcount,
+                script.results.interesting,
+                posterior.mean,
+                posterior.median,
+                posterior.p5,
+                posterior.p5,
+                posterior.p95
+            )?;
+            match script.mean_runtime_ms() {
+                Some(runtime) => write!(
+                    f,
+                    "<td class=\"number\" data-sort=\"{}\">{}</td>",
+                    runtime,
+                    format_duration_ms(runtime)
+                )?,
+                None => write!(f, "<td></td>")?,
+            }
+            write!(
+                f,
+                "<td class=\"number\">{}</td><td>{}</td><td>",
+                script.bias,
+                escape(
+                    &script
+                        .stop_reason(&self.priors[index])
+                        .unwrap_or_else(|| "Active".to_string())
+                )
+            )?;
+            for (i, artifact) in self.artifacts(script).iter().enumerate() {
+                let name = artifact
+                    .file_name()
+                    .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+                if i > 0 {
+                    write!(f, "<br>")?;
+                }
+                let href =
+                    relative_path(artifact, self.html_dir).unwrap_or_else(|| artifact.clone());
+                write!(
+                    f,
+                    "<a href=\"{}\">{}</a>",
+                    escape(&url_path(&href)),
+                    escape(&name)
+                )?;
+            }
+            writeln!(f, "</td></tr>")?;
+        }
+
+        writeln!(f, "</tbody>")?;
+        writeln!(f, "</table>")
+    }
+
+    fn write_signatures(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.signatures.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(f, "<h2>Failure signatures</h2>")?;
+        writeln!(f, "<table class=\"sortable\">")?;
+        writeln!(
+            f,
+            "<thead><tr><th>Signature</th><th>Runs</th><th>First found by</th></tr></thead>"
+        )?;
+        writeln!(f, "<tbody>")?;
+        for (signature, record) in self.signatures {
+            writeln!(
+                f,
+                "<tr><td><code>{}</code></td><td class=\"number\">{}</td><td>{}</td></tr>",
+                escape(signature),
+                record.count,
+                escape(&record.first_seen_by)
+            )?;
+        }
+        writeln!(f, "</tbody>")?;
+        writeln!(f, "</table>")
+    }
+
+    /// The files in the script's artifact directory, sorted by name.
+    fn artifacts(&self, script: &Script) -> Vec<PathBuf> {
+        let Some(entries) = self
+            .artifacts
+            .and_then(|artifacts| artifacts.join(&script.name).read_dir().ok())
+        else {
+            return vec![];
+        };
+
+        let mut artifacts = entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .collect::<Vec<_>>();
+        artifacts.sort();
+        artifacts
+    }
+}
+
+fn write_legend(
+    f: &mut fmt::Formatter<'_>,
+    chosen: &[(&Script, &Prior)],
+    colors: &[RGB8],
+) -> fmt::Result {
+    writeln!(f, "<ul class=\"legend\">")?;
+    for ((script, _), color) in chosen.iter().zip(colors) {
+        writeln!(
+            f,
+            "<li><span style=\"background: {}\"></span>{}</li>",
+            hex(*color),
+            escape(&script.name)
+        )?;
+    }
+    writeln!(f, "</ul>")
+}
+
+/// The density of each script's failure rate posterior, zoomed in like the terminal plots.
+fn posterior_chart<'a>(chosen: &[(&'a Script, &Prior)], colors: &[RGB8]) -> LineChart<'a> {
+    let curves = chosen
+        .iter()
+        .map(|&(script, prior)| {
+            (
+                script.results.interesting as f64 + prior.alpha,
+                script.results.uninteresting as f64 + prior.beta,
+                1.0,
+            )
+        })
+        .collect::<Vec<_>>();
+    let (low, high) = zoom_range(&curves);
+
+    let series = chosen
+        .iter()
+        .zip(colors)
+        .zip(&curves)
+        .map(|((&(script, _), &color), &(alpha, beta, _))| Series {
+            name: &script.name,
+            color,
+            points: (0..=CURVE_POINTS)
+                .map(|i| {
+                    let rate = low + (high - low) * i as f64 / CURVE_POINTS as f64;
+                    (rate, beta_density(alpha, beta, rate))
+                })
+                // The density is infinite at 0 for alpha below 1, and at 1 for beta below 1.
+                .filter(|(_, density)| density.is_finite())
+                .collect(),
+        })
+        .collect();
+
+    LineChart {
+        x_label: "failure rate",
+        y_label: "density",
+        x_range: (low, high),
+        series,
+    }
+}
+
+/// Mean runtime of each script from its runtime model, for scripts that have run.
+fn runtime_chart<'a>(chosen: &[(&'a Script, &Prior)], colors: &[RGB8]) -> BarChart<'a> {
+    BarChart {
+        label: "average runtime (ms)",
+        bars: chosen
+            .iter()
+            .zip(colors)
+            .filter_map(|(&(script, _), &color)| {
+                script
+                    .mean_runtime_ms()
+                    .map(|runtime| (script.name.as_str(), color, runtime))
+            })
+            .collect(),
+    }
+}
+
+struct Series<'a> {
+    name: &'a str,
+    color: RGB8,
+    points: Vec<(f64, f64)>,
+}
+
+/// An inline SVG line chart, with the y axis starting at 0.
+struct LineChart<'a> {
+    x_label: &'a str,
+    y_label: &'a str,
+    x_range: (f64, f64),
+    series: Vec<Series<'a>>,
+}
+
+impl fmt::Display for LineChart<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (left, right, top, bottom) = CHART_MARGINS;
+        let (x_low, x_high) = self.x_range;
+        let y_high = self
+            .series
+            .iter()
+            .flat_map(|series| &series.points)
+            .map(|&(_, y)| y)
+            .filter(|y| y.is_finite())
+            .fold(0.0, f64::max);
+        let y_high = if y_high > 0.0 { y_high } else { 1.0 };
+        let width = CHART_WIDTH - left - right;
+        let height = CHART_HEIGHT - top - bottom;
+        let x_at = |x: f64| left + (x - x_low) / (x_high - x_low) * width;
+        let y_at = |y: f64| top + height - y.min(y_high) / y_high * height;
+
+        writeln!(
+            f,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">",
+            CHART_WIDTH, CHART_HEIGHT, CHART_WIDTH, CHART_HEIGHT
+        )?;
+        write_axes(f, x_at(x_low), y_at(0.0), x_at(x_high), y_at(y_high))?;
+        for (value, anchor) in [(x_low, "start"), (x_high, "end")] {
+            writeln!(
+                f,
+                "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"{}\">{}</text>",
+                x_at(value),
+                top + height + 16.0,
+                anchor,
+                tick(value)
+            )?;
+        }
+        for value in [0.0, y_high] {
+            writeln!(
+                f,
+                "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>",
+                left - 6.0,
+                y_at(value) + 4.0,
+                tick(value)
+            )?;
+        }
+        writeln!(
+            f,
+            "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{}</text>",
+            left + width / 2.0,
+            CHART_HEIGHT - 12.0,
+            escape(self.x_label)
+        )?;
+        writeln!(
+            f,
+            "<text transform=\"translate(16 {:.1}) rotate(-90)\" text-anchor=\"middle\">{}</text>",
+            top + height / 2.0,
+            escape(self.y_label)
+        )?;
+
+        // Later elements paint over earlier ones, so the first series goes last.
+        for series in self.series.iter().rev() {
+            let points = series
+                .points
+                .iter()
+                .filter(|(_, y)| y.is_finite())
+                .map(|&(x, y)| format!("{:.1},{:.1}", x_at(x), y_at(y)))
+                .collect::<Vec<_>>();
+            writeln!(
+                f,
+                "<polyline fill=\"none\" stroke=\"{}\" stroke-width=\"2\" points=\"{}\"><title>{}</title></polyline>",
+                hex(series.color),
+                points.join(" "),
+                escape(series.name)
+            )?;
+        }
+        writeln!(f, "</svg>")
+    }
+}
+
+/// An inline SVG chart with a horizontal bar per value, starting at 0.
+struct BarChart<'a> {
+    label: &'a str,
+    bars: Vec<(&'a str, RGB8, f64)>,
+}
+
+impl fmt::Display for BarChart<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.bars.is_empty() {
+            return Ok(());
+        }
+
+        let (left, right, top, bottom) = CHART_MARGINS;
+        let height = top + bottom + BAR_HEIGHT * self.bars.len() as f64;
+        let width = CHART_WIDTH - left - right;
+        let high = self
+            .bars
+            .iter()
+            .map(|&(_, _, value)| value)
+            .fold(0.0, f64::max);
+        let high = if high > 0.0 { high } else { 1.0 };
+
+        writeln!(
+            f,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">",
+            CHART_WIDTH, height, CHART_WIDTH, height
+        )?;
+        write_axes(f, left, height - bottom, left + width, top)?;
+        for (i, &(name, color, value)) in self.bars.iter().enumerate() {
+            let y = top + BAR_HEIGHT * i as f64;
+            writeln!(
+                f,
+                "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\"><title>{}: {}</title></rect>",
+                left,
+                y + 2.0,
+                value / high * width,
+                BAR_HEIGHT - 4.0,
+                hex(color),
+                escape(name),
+                format_duration_ms(value)
+            )?;
+        }
+        for (value, anchor) in [(0.0, "start"), (high, "end")] {
+            writeln!(
+                f,
+                "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"{}\">{}</text>",
+                left + value / high * width,
+                height - bottom + 16.0,
+                anchor,
+                tick(value)
+            )?;
+        }
+        writeln!(
+            f,
+            "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{}</text>",
+            left + width / 2.0,
+            height - 12.0,
+            escape(self.label)
+        )?;
+        writeln!(f, "</svg>")
+    }
+}
+
+/// The x and y axes, meeting at the origin.
+fn write_axes(
+    f: &mut fmt::Formatter<'_>,
+    x_origin: f64,
+    y_origin: f64,
+    x_end: f64,
+    y_end: f64,
+) -> fmt::Result {
+    writeln!(
+        f,
+        "<path fill=\"none\" stroke=\"#222\" d=\"M{:.1},{:.1} L{:.1},{:.1} L{:.1},{:.1}\"/>",
+        x_origin, y_end, x_origin, y_origin, x_end, y_origin
+    )
+}
+
+/// Scientific notation for values too small or large to read otherwise, e.g. failure rates
+/// around 1e-4.
+fn tick(value: f64) -> String {
+    if value != 0.0 && !(0.01..1e5).contains(&value.abs()) {
+        format!("{:.2e}", value)
+    } else {
+        format!("{}", (value * 1000.0).round() / 1000.0)
+    }
+}
+
+fn hex(color: RGB8) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+/// `path` relative to `base`, both resolved through the file system. `None` if either can't be.
+fn relative_path(path: &Path, base: &Path) -> Option<PathBuf> {
+    let path = path.canonicalize().ok()?;
+    let base = base.canonicalize().ok()?;
+    let common = path
+        .components()
+        .zip(base.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut relative = PathBuf::new();
+    for _ in base.components().skip(common) {
+        relative.push("..");
+    }
+    relative.extend(path.components().skip(common));
+    Some(relative)
+}
+
+/// `path` as the path of a URL, with each segment percent-encoded.
+fn url_path(path: &Path) -> String {
+    path.components()
+        .map(|component| match component {
+            Component::RootDir => String::new(),
+            component => component
+                .as_os_str()
+                .as_bytes()
+                .iter()
+                .map(|&byte| match byte {
+                    b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                        (byte as char).to_string()
+                    }
+                    _ => format!("%{:02X}", byte),
+                })
+                .collect(),
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Escape text for HTML content and attribute values.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[test]
+fn test_relative_path() {
+    assert_eq!(
+        relative_path(Path::new("src/report.rs"), Path::new("tests")),
+        Some(PathBuf::from("../src/report.rs"))
+    );
+    assert_eq!(
+        relative_path(Path::new("./src/report.rs"), Path::new(".")),
+        Some(PathBuf::from("src/report.rs"))
+    );
+    assert_eq!(relative_path(Path::new("missing"), Path::new(".")), None);
+}
+
+#[test]
+fn test_url_path() {
+    assert_eq!(
+        url_path(Path::new("../out/bug #1/100% ü.log")),
+        "../out/bug%20%231/100%25%20%C3%BC.log"
+    );
+    assert_eq!(url_path(Path::new("/tmp/a b")), "/tmp/a%20b");
+}
+
+#[test]
+fn test_escape() {
+    assert_eq!(
+        escape(r#"<a href="x">Tom & 'Jerry'</a>"#),
+        "&lt;a href=&quot;x&quot;&gt;Tom &amp; &#39;Jerry&#39;&lt;/a&gt;"
+    );
+}
+
+#[test]
+fn test_tick() {
+    assert_eq!(tick(0.0), "0");
+    assert_eq!(tick(0.25), "0.25");
+    assert_eq!(tick(1.0 / 3.0), "0.333");
+    assert_eq!(tick(0.0001234), "1.23e-4");
+    assert_eq!(tick(250000.0), "2.50e5");
+}
+
+#[test]
+fn test_line_chart() {
+    let chart = LineChart {
+        x_label: "runs",
+        y_label: "share <of> runs",
+        x_range: (0.0, 10.0),
+        series: vec![Series {
+            name: "a",
+            color: RGB8 {
+                r: 255,
+                g: 0,
+                b: 16,
+            },
+            points: vec![(0.0, 0.0), (10.0, 2.0)],
+        }],
+    }
+    .to_string();
+
+    assert!(chart.starts_with("<svg "));
+    assert!(chart.contains("stroke=\"#ff0010\""));
+    // The y axis runs from 0 at the bottom to the highest point at the top.
+    assert!(chart.contains("points=\"80.0,270.0 700.0,20.0\""));
+    assert!(chart.contains("share &lt;of&gt; runs"));
+}
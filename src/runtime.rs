@@ -0,0 +1,389 @@
+use std::collections::BTreeMap;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, Script};
+
+/// Runtimes are measured in microseconds. Shorter runtimes are treated as one microsecond,
+/// so their logarithm stays finite and they don't divide scores by zero.
+pub const MIN_RUNTIME_MS: f64 = 0.001;
+
+/// Variance of the log runtime assumed before any spread has been observed.
+/// A variance of 1 means runtimes are believed to be within a factor of e of each other.
+const PRIOR_LOG_VARIANCE: f64 = 1.0;
+
+/// Log-normal model of a script's runtime, or of another resource its runs use.
+///
+/// Keeps the running mean and sum of squared deviations (Welford) of the log runtime, so the
+/// uncertainty about the typical runtime shrinks as the script is measured more often.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct RuntimeModel {
+    pub samples: u64,
+    pub log_mean: f64,
+    pub log_m2: f64,
+}
+
+impl RuntimeModel {
+    /// A model holding a single measurement, for scripts that only have an average runtime.
+    pub fn from_mean(mean_ms: f64) -> RuntimeModel {
+        let mut model = RuntimeModel::default();
+        model.update(mean_ms);
+        model
+    }
+
+    /// A model of a script that hasn't run yet, centred on the runtime it is expected to take.
+    /// It holds no measurements, so the first measurement replaces the estimate.
+    pub fn expected(runtime_ms: f64) -> RuntimeModel {
+        RuntimeModel {
+            samples: 0,
+            log_mean: runtime_ms.max(MIN_RUNTIME_MS).ln(),
+            log_m2: 0.0,
+        }
+    }
+
+    pub fn update(&mut self, runtime_ms: f64) {
+        let log_runtime = runtime_ms.max(MIN_RUNTIME_MS).ln();
+
+        self.samples += 1;
+        let delta = log_runtime - self.log_mean;
+        self.log_mean += delta / self.samples as f64;
+        self.log_m2 += delta * (log_runtime - self.log_mean);
+    }
+
+    /// Variance of the log runtime, shrunk towards the prior while there are few samples.
+    pub fn log_variance(&self) -> f64 {
+        (self.log_m2 + PRIOR_LOG_VARIANCE) / self.samples.max(1) as f64
+    }
+
+    /// Typical (geometric mean) runtime.
+    pub fn median_ms(&self) -> f64 {
+        self.log_mean.exp()
+    }
+
+    /// Mean runtime of the log-normal fitted to the measurements. The same as the median for
+    /// a single measurement or an estimate.
+    pub fn mean_ms(&self) -> f64 {
+        (self.log_mean + self.log_m2 / self.samples.max(1) as f64 / 2.0).exp()
+    }
+
+    /// Draw a typical runtime from the posterior of the log mean. Scripts measured only a few
+    /// times get widely spread samples, so uncertainty about their cost drives exploration.
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> f64 {
+        let standard_error = (self.log_variance() / self.samples.max(1) as f64).sqrt();
+        (self.log_mean + standard_error * standard_normal(rng)).exp()
+    }
+}
+
+/// A resource measured on every run.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum CostResource {
+    /// Wall-clock time in ms.
+    Wall,
+    /// User and system CPU time in ms.
+    Cpu,
+    /// Peak resident memory in MB multiplied by wall-clock time in seconds.
+    MemorySeconds,
+}
+
+/// What running a script costs: a single resource, or a weighted sum of several.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(untagged)]
+pub enum Cost {
+    Resource(CostResource),
+    Weighted(BTreeMap<CostResource, f64>),
+}
+
+impl Default for Cost {
+    fn default() -> Self {
+        Cost::Resource(CostResource::Wall)
+    }
+}
+
+impl Cost {
+    pub fn is_default(&self) -> bool {
+        *self == Cost::default()
+    }
+
+    pub fn weights(&self) -> Vec<(CostResource, f64)> {
+        match self {
+            Cost::Resource(resource) => vec![(*resource, 1.0)],
+            Cost::Weighted(weights) => weights
+                .iter()
+                .map(|(resource, weight)| (*resource, *weight))
+                .collect(),
+        }
+    }
+}
+
+/// The cost of a run, as a weighted sum of resource models.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CostModel {
+    parts: Vec<(f64, RuntimeModel)>,
+}
+
+impl From<RuntimeModel> for CostModel {
+    fn from(model: RuntimeModel) -> Self {
+        CostModel {
+            parts: vec![(1.0, model)],
+        }
+    }
+}
+
+impl CostModel {
+    /// Typical cost, from the typical use of every resource.
+    #[cfg(test)]
+    pub fn median(&self) -> f64 {
+        self.parts
+            .iter()
+            .map(|(weight, model)| weight * model.median_ms())
+            .sum()
+    }
+
+    /// Draw a cost, sampling every resource independently.
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> f64 {
+        self.parts
+            .iter()
+            .map(|(weight, model)| weight * model.sample(rng))
+            .sum()
+    }
+}
+
+/// Geometric mean of the typical values of the measured models.
+fn typical(models: &[Option<RuntimeModel>]) -> Option<f64> {
+    let measured = models.iter().flatten().collect::<Vec<_>>();
+    (!measured.is_empty()).then(|| {
+        (measured.iter().map(|model| model.log_mean).sum::<f64>() / measured.len() as f64).exp()
+    })
+}
+
+/// Every script's model of a resource other than wall-clock time. Scripts that haven't
+/// measured it yet are expected to use as much as the typical script that has.
+fn measured_or_typical(
+    scripts: &[Script],
+    resource: fn(&Script) -> Option<RuntimeModel>,
+) -> Vec<Option<RuntimeModel>> {
+    let models = scripts.iter().map(resource).collect::<Vec<_>>();
+    let typical = typical(&models);
+
+    models
+        .into_iter()
+        .map(|model| model.or(typical.map(RuntimeModel::expected)))
+        .collect()
+}
+
+/// The cost model of every script, combining the resources picked by the config's `cost`.
+/// A resource no script has measured yet is left out.
+pub fn script_costs(config: &Config) -> Vec<Option<CostModel>> {
+    let weights = config.cost.weights();
+    let models = weights
+        .iter()
+        .map(|(resource, _)| match resource {
+            CostResource::Wall => script_runtimes(config),
+            CostResource::Cpu => measured_or_typical(&config.scripts, |script| script.cpu_time),
+            CostResource::MemorySeconds => {
+                measured_or_typical(&config.scripts, |script| script.memory_mb_s)
+            }
+        })
+        .collect::<Vec<_>>();
+
+    (0..config.scripts.len())
+        .map(|index| {
+            let parts = weights
+                .iter()
+                .zip(&models)
+                .filter_map(|((_, weight), models)| models[index].map(|model| (*weight, model)))
+                .collect::<Vec<_>>();
+            (!parts.is_empty()).then_some(CostModel { parts })
+        })
+        .collect()
+}
+
+/// The runtime model of every script. Scripts that haven't run yet get a cold-start estimate
+/// from the first of: their own `expected_runtime_ms`, the largest `tag_expected_runtime_ms`
+/// among their tags, the config's `expected_runtime_ms`, and the typical runtime of the
+/// scripts that have run. `None` when there is nothing to estimate from.
+pub fn script_runtimes(config: &Config) -> Vec<Option<RuntimeModel>> {
+    let typical_ms = typical(
+        &config
+            .scripts
+            .iter()
+            .map(Script::runtime_model)
+            .collect::<Vec<_>>(),
+    );
+
+    config
+        .scripts
+        .iter()
+        .map(|script| {
+            script.runtime_model().or_else(|| {
+                script
+                    .expected_runtime_ms
+                    .or_else(|| {
+                        script
+                            .tags
+                            .iter()
+                            .filter_map(|tag| config.tag_expected_runtime_ms.get(tag).copied())
+                            .reduce(f64::max)
+                    })
+                    .or(config.expected_runtime_ms)
+                    .or(typical_ms)
+                    .map(RuntimeModel::expected)
+            })
+        })
+        .collect()
+}
+
+/// Box-Muller transform.
+fn standard_normal<R: Rng>(rng: &mut R) -> f64 {
+    // 1 - [0, 1) keeps the logarithm finite.
+    let u1: f64 = 1.0 - rng.gen::<f64>();
+    let u2: f64 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+#[test]
+fn test_runtime_model_welford() {
+    let mut model = RuntimeModel::default();
+    for runtime_ms in [1.0, 10.0, 100.0] {
+        model.update(runtime_ms);
+    }
+
+    assert_eq!(model.samples, 3);
+    assert!((model.median_ms() - 10.0).abs() < 1e-9);
+    // Sample variance of ln(1), ln(10), ln(100) is ln(10)^2.
+    assert!((model.log_m2 / 2.0 - 10f64.ln().powi(2)).abs() < 1e-9);
+}
+
+#[test]
+fn test_runtime_model_mean() {
+    let mut model = RuntimeModel::from_mean(100.0);
+    assert!((model.mean_ms() - 100.0).abs() < 1e-9);
+
+    model.update(100.0);
+    assert!((model.mean_ms() - 100.0).abs() < 1e-9);
+
+    // Spread pulls the mean above the median.
+    model.update(1000.0);
+    assert!(model.mean_ms() > model.median_ms());
+}
+
+#[test]
+fn test_runtime_model_zero_runtime() {
+    let model = RuntimeModel::from_mean(0.0);
+
+    assert!(model.log_mean.is_finite());
+    assert!((model.median_ms() - MIN_RUNTIME_MS).abs() < 1e-12);
+}
+
+#[test]
+fn test_runtime_model_uncertainty_shrinks() {
+    let spread = |model: &RuntimeModel| {
+        let mut rng = rand::thread_rng();
+        let samples = (0..1000)
+            .map(|_| model.sample(&mut rng).ln())
+            .collect::<Vec<_>>();
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / samples.len() as f64
+    };
+
+    let once = RuntimeModel::from_mean(50.0);
+    let mut often = RuntimeModel::default();
+    for _ in 0..100 {
+        often.update(50.0);
+    }
+
+    assert!(spread(&once) > 0.5);
+    assert!(spread(&often) < 0.01);
+}
+
+#[test]
+fn test_expected_runtime_is_replaced() {
+    let mut model = RuntimeModel::expected(1000.0);
+    model.update(10.0);
+
+    assert_eq!(model.samples, 1);
+    assert!((model.median_ms() - 10.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_script_runtimes_cold_start() {
+    let mut config = Config {
+        expected_runtime_ms: Some(50.0),
+        tag_expected_runtime_ms: BTreeMap::from([
+            ("slow".to_string(), 2000.0),
+            ("network".to_string(), 500.0),
+        ]),
+        ..Config::default()
+    };
+    let mut measured = Script::new("measured".to_string(), "ls".to_string());
+    measured.runtime = Some(RuntimeModel::from_mean(4.0));
+    let mut own = Script::new("own".to_string(), "ls".to_string());
+    own.expected_runtime_ms = Some(7.0);
+    own.tags = vec!["slow".to_string()];
+    let mut tagged = Script::new("tagged".to_string(), "ls".to_string());
+    tagged.tags = vec!["network".to_string(), "slow".to_string()];
+    let untagged = Script::new("untagged".to_string(), "ls".to_string());
+    config.scripts = vec![measured, own, tagged, untagged];
+
+    let medians = script_runtimes(&config)
+        .iter()
+        .map(|model| model.unwrap().median_ms().round())
+        .collect::<Vec<_>>();
+    assert_eq!(medians, vec![4.0, 7.0, 2000.0, 50.0]);
+
+    // Without settings, unmeasured scripts are expected to take as long as the measured ones.
+    config.expected_runtime_ms = None;
+    assert_eq!(
+        script_runtimes(&config)[3].unwrap().median_ms().round(),
+        4.0
+    );
+
+    config.scripts.remove(0);
+    assert_eq!(script_runtimes(&config)[2], None);
+}
+
+#[test]
+fn test_script_costs() {
+    let mut measured = Script::new("measured".to_string(), "ls".to_string());
+    measured.runtime = Some(RuntimeModel::from_mean(100.0));
+    measured.cpu_time = Some(RuntimeModel::from_mean(10.0));
+    let mut wall_only = Script::new("wall only".to_string(), "ls".to_string());
+    wall_only.runtime = Some(RuntimeModel::from_mean(1000.0));
+    let mut config = Config {
+        scripts: vec![measured, wall_only],
+        ..Config::default()
+    };
+
+    let medians = |config: &Config| {
+        script_costs(config)
+            .iter()
+            .map(|cost| cost.as_ref().unwrap().median().round())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(medians(&config), vec![100.0, 1000.0]);
+
+    config.cost = Cost::Resource(CostResource::Cpu);
+    assert_eq!(medians(&config), vec![10.0, 10.0]);
+
+    config.cost = Cost::Weighted(BTreeMap::from([
+        (CostResource::Wall, 1.0),
+        (CostResource::Cpu, 2.0),
+        (CostResource::MemorySeconds, 5.0),
+    ]));
+    // Memory hasn't been measured for any script, so it is left out.
+    assert_eq!(medians(&config), vec![120.0, 1020.0]);
+}
+
+#[test]
+fn test_cost_config() {
+    let cost: Cost = serde_json::from_str("\"memory_seconds\"").unwrap();
+    assert_eq!(cost, Cost::Resource(CostResource::MemorySeconds));
+
+    let cost: Cost = serde_json::from_str(r#"{"wall": 1, "cpu": 0.5}"#).unwrap();
+    assert_eq!(
+        cost.weights(),
+        vec![(CostResource::Wall, 1.0), (CostResource::Cpu, 0.5)]
+    );
+}
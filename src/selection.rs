@@ -0,0 +1,241 @@
+use ordered_float::NotNan;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    config::Script,
+    prior::Prior,
+    runtime::CostModel,
+    thompson::{
+        rank_by_score, thompson_sampling, thompson_sampling_bias_runtime, thompson_scores,
+        thompson_scores_bias_runtime, ThompsonInfo,
+    },
+};
+
+/// A script's position in the list of scripts it was selected from, e.g. `config.scripts`.
+///
+/// Sampling and ranking work on the candidates only. [`Candidates`] maps positions among the
+/// candidates back to ids, so they never index the full list by mistake.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ScriptId(pub usize);
+
+/// Seed for probability-of-being-best estimates, so the same config always ranks the same.
+const BEST_ARM_SEED: u64 = 0;
+
+/// Monte Carlo estimate of how likely a script is to be the best one to run.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BestArmEstimate {
+    /// Share of the draws in which the script scored highest.
+    pub probability: f64,
+    /// Bounds of the 95% Wilson score interval of `probability`. Unlike a normal
+    /// approximation, the interval doesn't collapse to a point for scripts that never or
+    /// always win.
+    pub low: f64,
+    pub high: f64,
+    /// Mean score over the draws, adjusted for runtime unless runtime is ignored.
+    pub mean_score: f64,
+}
+
+/// The active scripts that may run, with their results, priors, costs and biases kept
+/// aligned with each other.
+pub struct Candidates<'a> {
+    ids: Vec<ScriptId>,
+    results: Vec<&'a ThompsonInfo>,
+    priors: Vec<Prior>,
+    costs: Vec<Option<CostModel>>,
+    biases: Vec<&'a NotNan<f64>>,
+}
+
+impl<'a> Candidates<'a> {
+    /// The scripts that haven't stopped and that `include` accepts. `priors` and `costs`
+    /// are aligned with `scripts`.
+    pub fn active(
+        scripts: &'a [Script],
+        priors: &[Prior],
+        mut costs: Vec<Option<CostModel>>,
+        include: impl Fn(ScriptId, &Script) -> bool,
+    ) -> Candidates<'a> {
+        let ids = scripts
+            .iter()
+            .zip(priors)
+            .enumerate()
+            .filter(|&(index, (script, prior))| {
+                script.stop_reason(prior).is_none() && include(ScriptId(index), script)
+            })
+            .map(|(index, _)| ScriptId(index))
+            .collect::<Vec<_>>();
+
+        Candidates {
+            results: ids.iter().map(|id| &scripts[id.0].results).collect(),
+            priors: ids.iter().map(|id| priors[id.0]).collect(),
+            costs: ids.iter().map(|id| costs[id.0].take()).collect(),
+            biases: ids.iter().map(|id| &scripts[id.0].bias).collect(),
+            ids,
+        }
+    }
+
+    /// Draw the script to run next.
+    pub fn sample(&self, ignore_runtime: bool) -> Option<ScriptId> {
+        let selected = if ignore_runtime {
+            thompson_sampling(&self.results, &self.priors, &self.biases)
+        } else {
+            thompson_sampling_bias_runtime(&self.results, &self.priors, &self.costs, &self.biases)
+        };
+
+        selected.map(|position| self.ids[position])
+    }
+
+    /// Rank the scripts from a single draw, best first.
+    pub fn rank(&self, ignore_runtime: bool) -> Vec<ScriptId> {
+        self.rank_with_scores(ignore_runtime)
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    /// Rank the scripts from a single draw, best first, along with the score each drew.
+    pub fn rank_with_scores(&self, ignore_runtime: bool) -> Vec<(ScriptId, f64)> {
+        let scores = self.scores(ignore_runtime, &mut rand::thread_rng());
+
+        rank_by_score(&scores)
+            .into_iter()
+            .map(|position| (self.ids[position], f64::from(scores[position])))
+            .collect()
+    }
+
+    /// Rank the scripts by their probability of being the best, estimated from `samples`
+    /// draws. The draws are seeded, so the ranking is the same on every call.
+    pub fn rank_by_best_arm(
+        &self,
+        ignore_runtime: bool,
+        samples: usize,
+    ) -> Vec<(ScriptId, BestArmEstimate)> {
+        let mut rng = StdRng::seed_from_u64(BEST_ARM_SEED);
+        let mut wins = vec![0u64; self.ids.len()];
+        let mut total_scores = vec![0.0; self.ids.len()];
+        for _ in 0..samples {
+            let scores = self.scores(ignore_runtime, &mut rng);
+            if let Some(&best) = rank_by_score(&scores).first() {
+                wins[best] += 1;
+            }
+            for (total, score) in total_scores.iter_mut().zip(&scores) {
+                *total += f64::from(*score);
+            }
+        }
+
+        let samples = samples.max(1) as f64;
+        let mut ranking = self
+            .ids
+            .iter()
+            .zip(wins.iter().zip(total_scores))
+            .map(|(&id, (&wins, total_score))| {
+                let probability = wins as f64 / samples;
+                let (low, high) = wilson_interval(probability, samples);
+                let estimate = BestArmEstimate {
+                    probability,
+                    low,
+                    high,
+                    mean_score: total_score / samples,
+                };
+                (id, estimate)
+            })
+            .collect::<Vec<_>>();
+        ranking.sort_by(|(_, a), (_, b)| {
+            b.probability
+                .total_cmp(&a.probability)
+                .then(b.mean_score.total_cmp(&a.mean_score))
+        });
+        ranking
+    }
+
+    /// One score per candidate from a single draw.
+    fn scores(&self, ignore_runtime: bool, rng: &mut impl Rng) -> Vec<NotNan<f64>> {
+        if ignore_runtime {
+            thompson_scores(&self.results, &self.priors, rng)
+        } else {
+            thompson_scores_bias_runtime(
+                &self.results,
+                &self.priors,
+                &self.costs,
+                &self.biases,
+                rng,
+            )
+        }
+    }
+}
+
+/// 95% Wilson score interval for a proportion observed in `samples` trials.
+fn wilson_interval(proportion: f64, samples: f64) -> (f64, f64) {
+    const Z: f64 = 1.96;
+    let z_squared = Z * Z;
+    let denominator = 1.0 + z_squared / samples;
+    let centre = (proportion + z_squared / (2.0 * samples)) / denominator;
+    let half_width = Z
+        * (proportion * (1.0 - proportion) / samples + z_squared / (4.0 * samples * samples))
+            .sqrt()
+        / denominator;
+    (
+        (centre - half_width).max(0.0),
+        (centre + half_width).min(1.0),
+    )
+}
+
+#[test]
+fn test_candidates_map_back_to_scripts() {
+    let mut scripts = vec![
+        Script::new("a".to_string(), "ls".to_string()),
+        Script::new("b".to_string(), "ls".to_string()),
+        Script::new("c".to_string(), "ls".to_string()),
+        Script::new("d".to_string(), "ls".to_string()),
+    ];
+    // a and c have reached their limit of one interesting result.
+    for stopped in [0, 2] {
+        scripts[stopped].limit = Some(1);
+        scripts[stopped].results.interesting = 1;
+    }
+    let priors = vec![Prior::default(); scripts.len()];
+
+    let candidates = Candidates::active(&scripts, &priors, vec![None; scripts.len()], |_, _| true);
+    let mut ranking = candidates.rank(false);
+    ranking.sort();
+    assert_eq!(ranking, vec![ScriptId(1), ScriptId(3)]);
+
+    let only_d = Candidates::active(&scripts, &priors, vec![None; scripts.len()], |_, script| {
+        script.name == "d"
+    });
+    for ignore_runtime in [false, true] {
+        assert_eq!(only_d.sample(ignore_runtime), Some(ScriptId(3)));
+        assert_eq!(only_d.rank(ignore_runtime), vec![ScriptId(3)]);
+    }
+}
+
+#[test]
+fn test_rank_by_best_arm() {
+    let mut scripts = vec![
+        Script::new("rare".to_string(), "ls".to_string()),
+        Script::new("common".to_string(), "ls".to_string()),
+    ];
+    scripts[0].results.uninteresting = 100;
+    scripts[1].results.interesting = 50;
+    scripts[1].results.uninteresting = 50;
+    let priors = vec![Prior::default(); scripts.len()];
+
+    let candidates = Candidates::active(&scripts, &priors, vec![None, None], |_, _| true);
+    let ranking = candidates.rank_by_best_arm(true, 1000);
+    assert_eq!(ranking[0].0, ScriptId(1));
+    assert!(ranking[0].1.probability > 0.99);
+    assert!(ranking[0].1.mean_score > ranking[1].1.mean_score);
+    let total = ranking
+        .iter()
+        .map(|(_, estimate)| estimate.probability)
+        .sum::<f64>();
+    assert!((total - 1.0).abs() < 1e-9);
+
+    // A script that never wins still gets an interval with a positive upper bound.
+    assert_eq!(ranking[1].1.probability, 0.0);
+    assert_eq!(ranking[1].1.low, 0.0);
+    assert!(ranking[1].1.high > 0.003, "{:?}", ranking[1].1);
+    assert!(ranking[0].1.low < ranking[0].1.probability);
+
+    // Seeded, so repeated estimates agree.
+    assert_eq!(ranking, candidates.rank_by_best_arm(true, 1000));
+}
@@ -0,0 +1,306 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::Write,
+    process::{Command, Output, Stdio},
+    thread,
+};
+
+use regex::Regex;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Identifies the bug behind a failure, so repeated failures from a known bug don't count as
+/// interesting. Signatures are extracted from the run's stderr followed by its stdout.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureExtractor {
+    /// The first match of the regex, or its first capture group if it has one.
+    Regex(SignatureRegex),
+    /// A hash of the first N stack frames, with addresses left out.
+    StackHash(usize),
+    /// A command that reads the output on stdin and prints the signature.
+    Command(String),
+}
+
+/// A regex compiled when the config is loaded, so an invalid one is reported right away and
+/// it isn't compiled again for every run. Saved as its pattern.
+#[derive(Clone, Debug)]
+pub struct SignatureRegex(Regex);
+
+impl SignatureRegex {
+    pub fn new(pattern: &str) -> Result<SignatureRegex, regex::Error> {
+        Regex::new(pattern).map(SignatureRegex)
+    }
+}
+
+impl PartialEq for SignatureRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl fmt::Display for SignatureRegex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.as_str())
+    }
+}
+
+impl Serialize for SignatureRegex {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for SignatureRegex {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        SignatureRegex::new(&pattern)
+            .map_err(|e| de::Error::custom(format!("Invalid signature regex {}: {}", pattern, e)))
+    }
+}
+
+/// A signature in the signature database.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct SignatureRecord {
+    /// The script that found the signature first.
+    pub first_seen_by: String,
+    /// Runs of any script that failed with the signature.
+    pub count: u64,
+}
+
+impl SignatureExtractor {
+    /// The signature of a failed run, or `None` if the extractor doesn't recognise one.
+    pub fn extract(&self, output: &Output) -> Option<String> {
+        self.extract_text(
+            &[
+                String::from_utf8_lossy(&output.stderr),
+                String::from_utf8_lossy(&output.stdout),
+            ]
+            .join("\n"),
+        )
+    }
+
+    fn extract_text(&self, text: &str) -> Option<String> {
+        let signature = match self {
+            SignatureExtractor::Regex(SignatureRegex(regex)) => {
+                let captures = regex.captures(text)?;
+                captures
+                    .get(1)
+                    .or_else(|| captures.get(0))
+                    .map(|found| found.as_str().to_string())
+            }
+            SignatureExtractor::StackHash(frames) => stack_hash(text, *frames),
+            SignatureExtractor::Command(command) => run_extractor(command, text),
+        }?;
+
+        let signature = signature.trim().to_string();
+        (!signature.is_empty()).then_some(signature)
+    }
+}
+
+/// Record a failure signature found by `script` in the signature database. Returns whether
+/// the signature is new.
+pub fn record_signature(
+    database: &mut BTreeMap<String, SignatureRecord>,
+    signature: &str,
+    script: &str,
+) -> bool {
+    match database.get_mut(signature) {
+        Some(record) => {
+            record.count += 1;
+            false
+        }
+        None => {
+            database.insert(
+                signature.to_string(),
+                SignatureRecord {
+                    first_seen_by: script.to_string(),
+                    count: 1,
+                },
+            );
+            true
+        }
+    }
+}
+
+/// Lines that look like stack frames from common backtraces: `#3 0x4005d4 in main` from gdb
+/// and sanitizers, and indented `3: crate::main` from Rust and `at main.rs:10` from Rust, Java
+/// and JavaScript. Unindented lines like `12:03:44 started` or `1: ok` aren't frames.
+fn is_stack_frame(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    let indented = trimmed.len() < line.len();
+    let numbered = |rest: &str, separator: &str| {
+        let digits = rest.chars().take_while(char::is_ascii_digit).count();
+        digits > 0 && rest[digits..].starts_with(separator)
+    };
+
+    trimmed
+        .strip_prefix('#')
+        .is_some_and(|rest| numbered(rest, " "))
+        || (indented && (numbered(trimmed, ": ") || trimmed.starts_with("at ")))
+}
+
+/// Hash of the first `frames` stack frames. Addresses and offsets differ between runs of the
+/// same bug, so hex numbers are left out.
+fn stack_hash(text: &str, frames: usize) -> Option<String> {
+    let hex = Regex::new(r"0x[0-9a-fA-F]+").unwrap();
+    let frames = text
+        .lines()
+        .filter(|line| is_stack_frame(line))
+        .take(frames)
+        .map(|line| hex.replace_all(line.trim(), "").to_string())
+        .collect::<Vec<_>>();
+    if frames.is_empty() {
+        return None;
+    }
+
+    Some(format!(
+        "stack-{:016x}",
+        fnv1a(frames.join("\n").as_bytes())
+    ))
+}
+
+/// FNV-1a, which unlike the standard library's hasher is the same on every build, so hashes
+/// in the signature database stay valid.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Run the signature command on the text. If it can't be run, the run gets no signature
+/// rather than losing the results of every run so far.
+fn run_extractor(command: &str, text: &str) -> Option<String> {
+    let warn = |err: std::io::Error| {
+        println!(
+            "Warning: Failed to run signature command {}: {}. The run has no signature.",
+            command, err
+        );
+    };
+
+    let mut parts = command.split_whitespace();
+    let mut child = Command::new(parts.next()?)
+        .args(parts)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(warn)
+        .ok()?;
+
+    // Write from another thread while the output is read, so an extractor that prints a lot
+    // before reading its input can't deadlock. It may also exit without reading everything,
+    // which is fine.
+    let writer = child.stdin.take().map(|mut stdin| {
+        let text = text.to_string();
+        thread::spawn(move || {
+            stdin.write_all(text.as_bytes()).ok();
+        })
+    });
+    let output = child.wait_with_output().map_err(warn);
+    if let Some(writer) = writer {
+        writer.join().ok();
+    }
+    let output = output.ok()?;
+
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[test]
+fn test_regex_signature() {
+    let extractor = SignatureExtractor::Regex(SignatureRegex::new("panicked at (\\S+)").unwrap());
+
+    assert_eq!(
+        extractor.extract_text("thread 'main' panicked at src/parse.rs:10:5:\nindex out of bounds"),
+        Some("src/parse.rs:10:5:".to_string())
+    );
+    assert_eq!(extractor.extract_text("Segmentation fault"), None);
+}
+
+#[test]
+fn test_stack_hash_ignores_addresses() {
+    let extractor = SignatureExtractor::StackHash(2);
+    let first = "ERROR: AddressSanitizer: heap-use-after-free\n    #0 0x4005d4 in parse src/parse.c:10\n    #1 0x400710 in main src/main.c:3\n    #2 0x7f00 in __libc_start_main";
+    let second = "ERROR: AddressSanitizer: heap-use-after-free\n    #0 0x5115e4 in parse src/parse.c:10\n    #1 0x511820 in main src/main.c:3\n    #2 0x7f99 in __libc_start_main";
+    let other = "    #0 0x4005d4 in lex src/lex.c:7\n    #1 0x400710 in main src/main.c:3";
+
+    let signature = extractor.extract_text(first);
+    assert!(signature.is_some());
+    assert_eq!(signature, extractor.extract_text(second));
+    assert_ne!(signature, extractor.extract_text(other));
+    assert_eq!(extractor.extract_text("no backtrace"), None);
+}
+
+#[test]
+fn test_is_stack_frame() {
+    assert!(is_stack_frame("#0  0x4005d4 in main () at main.c:3"));
+    assert!(is_stack_frame("    #12 0x4005d4 in parse src/parse.c:10"));
+    assert!(is_stack_frame("   3: bts::main"));
+    assert!(is_stack_frame("             at ./src/bts.rs:1112:30"));
+    assert!(is_stack_frame("\tat com.example.Main.main(Main.java:5)"));
+
+    assert!(!is_stack_frame("12:03:44 starting"));
+    assert!(!is_stack_frame("1: ok"));
+    assert!(!is_stack_frame("  10:30 elapsed"));
+    assert!(!is_stack_frame("at least one test failed"));
+    assert!(!is_stack_frame("#include <stdio.h>"));
+}
+
+#[test]
+fn test_missing_command_has_no_signature() {
+    let extractor = SignatureExtractor::Command("./scripts/does-not-exist.sh".to_string());
+
+    assert_eq!(extractor.extract_text("first line"), None);
+}
+
+#[test]
+fn test_command_signature() {
+    let extractor = SignatureExtractor::Command("head -n 1".to_string());
+
+    assert_eq!(
+        extractor.extract_text("first line\nsecond line"),
+        Some("first line".to_string())
+    );
+}
+
+#[test]
+fn test_regex_signature_compiled_on_load() {
+    let extractor: SignatureExtractor =
+        serde_json::from_str(r#"{ "regex": "panicked at (\\S+)" }"#).unwrap();
+    assert_eq!(
+        serde_json::to_string(&extractor).unwrap(),
+        r#"{"regex":"panicked at (\\S+)"}"#
+    );
+
+    let invalid = serde_json::from_str::<SignatureExtractor>(r#"{ "regex": "(" }"#);
+    assert!(invalid
+        .unwrap_err()
+        .to_string()
+        .contains("Invalid signature regex ("));
+}
+
+#[test]
+fn test_command_signature_large_output() {
+    let extractor = SignatureExtractor::Command("./scripts/chatty_extractor.sh".to_string());
+
+    let signature = extractor.extract_text(&"y".repeat(200_000));
+    assert!(signature.is_some_and(|signature| signature.ends_with(&"y".repeat(200_000))));
+}
+
+#[test]
+fn test_record_signature() {
+    let mut database = BTreeMap::new();
+
+    assert!(record_signature(&mut database, "overflow", "a"));
+    assert!(!record_signature(&mut database, "overflow", "b"));
+    assert!(record_signature(&mut database, "use after free", "b"));
+    assert_eq!(
+        database["overflow"],
+        SignatureRecord {
+            first_seen_by: "a".to_string(),
+            count: 2
+        }
+    );
+}
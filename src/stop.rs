@@ -0,0 +1,167 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Script, prior::Prior, thompson::dist_area_at_percentile};
+
+/// Credibility of the upper bound on the failure rate used by `failure_rate_below`.
+pub const UPPER_BOUND_CREDIBILITY: f64 = 0.95;
+
+/// When a script has done enough and stops running. A script stops once any rule is met.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct StopRules {
+    /// Interesting results to collect.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_interesting: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_runs: Option<u64>,
+    /// Wall-clock time spent running the script, over all runs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_runtime_ms: Option<f64>,
+    /// Distinct failure signatures the script hit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_signatures: Option<u64>,
+    /// Stop once the failure rate is below this with 95% credibility, i.e. once the 95th
+    /// percentile of the posterior is.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure_rate_below: Option<f64>,
+}
+
+impl StopRules {
+    pub fn is_empty(&self) -> bool {
+        *self == StopRules::default()
+    }
+
+    /// These rules, falling back to `parent` for anything they don't set.
+    pub fn inherit(&self, parent: &StopRules) -> StopRules {
+        StopRules {
+            max_interesting: self.max_interesting.or(parent.max_interesting),
+            max_runs: self.max_runs.or(parent.max_runs),
+            max_runtime_ms: self.max_runtime_ms.or(parent.max_runtime_ms),
+            max_signatures: self.max_signatures.or(parent.max_signatures),
+            failure_rate_below: self.failure_rate_below.or(parent.failure_rate_below),
+        }
+    }
+
+    /// Why the script stopped, or `None` if it should keep running.
+    pub fn reason(&self, script: &Script, prior: &Prior) -> Option<String> {
+        let interesting = script.results.interesting;
+        if let Some(limit) = script.limit.filter(|&limit| interesting >= limit) {
+            return Some(format!(
+                "Found {} interesting results (limit {})",
+                interesting, limit
+            ));
+        }
+        if let Some(max) = self.max_interesting.filter(|&max| interesting >= max) {
+            return Some(format!(
+                "Found {} interesting results (max_interesting {})",
+                interesting, max
+            ));
+        }
+        if let Some(max) = self.max_runs.filter(|&max| script.runcount >= max) {
+            return Some(format!("Ran {} times (max_runs {})", script.runcount, max));
+        }
+
+        // Runs killed at their timeout aren't in the runtime model, but took about as long as
+        // the timeout.
+        let timeouts = script.timeouts();
+        let total_runtime_ms = script.mean_runtime_ms().unwrap_or(0.0)
+            * script.runcount.saturating_sub(timeouts) as f64
+            + script.timeout_ms.unwrap_or(0) as f64 * timeouts as f64;
+        if let Some(max) = self.max_runtime_ms.filter(|&max| total_runtime_ms >= max) {
+            return Some(format!(
+                "Ran for {:.0}ms in total (max_runtime_ms {})",
+                total_runtime_ms, max
+            ));
+        }
+
+        let signatures = script.signatures.len() as u64;
+        if let Some(max) = self.max_signatures.filter(|&max| signatures >= max) {
+            return Some(format!(
+                "Hit {} failure signatures (max_signatures {})",
+                signatures, max
+            ));
+        }
+
+        if let Some(below) = self.failure_rate_below {
+            let upper_bound =
+                dist_area_at_percentile(&script.results, prior, UPPER_BOUND_CREDIBILITY);
+            if upper_bound < below {
+                return Some(format!(
+                    "Failure rate is below {:.3e} with {:.0}% credibility (failure_rate_below {})",
+                    upper_bound,
+                    UPPER_BOUND_CREDIBILITY * 100.0,
+                    below
+                ));
+            }
+        }
+
+        None
+    }
+}
+
+#[test]
+fn test_stop_reason() {
+    let mut script = Script::new("a".to_string(), "ls".to_string());
+    script.runcount = 10;
+    script.results.interesting = 2;
+    script.results.uninteresting = 8;
+    script.avgruntime_ms = Some(ordered_float::NotNan::new(100.0).unwrap());
+    let prior = Prior::default();
+
+    assert_eq!(StopRules::default().reason(&script, &prior), None);
+
+    // The legacy limit only stops the script once it is reached.
+    script.limit = Some(3);
+    assert_eq!(StopRules::default().reason(&script, &prior), None);
+    script.limit = Some(2);
+    assert!(StopRules::default().reason(&script, &prior).is_some());
+    script.limit = None;
+
+    let stops = |rules: StopRules| rules.reason(&script, &prior).is_some();
+    assert!(stops(StopRules {
+        max_runs: Some(10),
+        ..Default::default()
+    }));
+    assert!(!stops(StopRules {
+        max_runs: Some(11),
+        ..Default::default()
+    }));
+    assert!(stops(StopRules {
+        max_runtime_ms: Some(1000.0),
+        ..Default::default()
+    }));
+    assert!(!stops(StopRules {
+        max_runtime_ms: Some(1001.0),
+        ..Default::default()
+    }));
+    assert!(!stops(StopRules {
+        max_signatures: Some(1),
+        ..Default::default()
+    }));
+
+    // Two of the runs timed out after 500ms each: 8 * 100ms + 2 * 500ms.
+    script.outcomes.insert("timeout".to_string(), 2);
+    script.timeout_ms = Some(500);
+    let rules = |max| StopRules {
+        max_runtime_ms: Some(max),
+        ..Default::default()
+    };
+    assert!(rules(1800.0).reason(&script, &prior).is_some());
+    assert!(rules(1801.0).reason(&script, &prior).is_none());
+}
+
+#[test]
+fn test_stop_failure_rate_below() {
+    let mut script = Script::new("a".to_string(), "ls".to_string());
+    let rules = StopRules {
+        failure_rate_below: Some(0.01),
+        ..Default::default()
+    };
+    let prior = Prior::default();
+
+    script.results.uninteresting = 100;
+    assert_eq!(rules.reason(&script, &prior), None);
+
+    // Beta(1, 1001) has its 95th percentile at about 0.003.
+    script.results.uninteresting = 1000;
+    assert!(rules.reason(&script, &prior).is_some());
+}
@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Script;
+
+/// Selects scripts by tag. Scripts must have one of `tags`, if any are given,
+/// and none of `exclude_tags`.
+#[derive(Clone, Debug, Default)]
+pub struct TagFilter {
+    pub tags: Vec<String>,
+    pub exclude_tags: Vec<String>,
+}
+
+impl TagFilter {
+    pub fn matches(&self, script: &Script) -> bool {
+        let included =
+            self.tags.is_empty() || self.tags.iter().any(|tag| script.tags.contains(tag));
+        let excluded = self
+            .exclude_tags
+            .iter()
+            .any(|tag| script.tags.contains(tag));
+
+        included && !excluded
+    }
+}
+
+/// Upper bounds on the share of a single `bts run` spent on scripts with a tag.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct Budget {
+    /// Maximum share of the runs, between 0 and 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_run_share: Option<f64>,
+    /// Maximum share of the wall-clock time spent running scripts, between 0 and 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_time_share: Option<f64>,
+}
+
+/// Runs and time spent per tag during the current `bts run`.
+#[derive(Debug, Default)]
+pub struct BudgetUsage {
+    runs: BTreeMap<String, u64>,
+    total_runs: u64,
+    runtime_ms: BTreeMap<String, f64>,
+    total_runtime_ms: f64,
+}
+
+impl BudgetUsage {
+    pub fn record(&mut self, script: &Script, runtime_ms: f64) {
+        self.total_runs += 1;
+        self.total_runtime_ms += runtime_ms;
+        for tag in &script.tags {
+            *self.runs.entry(tag.clone()).or_default() += 1;
+            *self.runtime_ms.entry(tag.clone()).or_default() += runtime_ms;
+        }
+    }
+
+    /// Whether running the script once more, taking about `estimated_ms`, keeps every one of
+    /// its tags within budget.
+    pub fn allows(
+        &self,
+        budgets: &BTreeMap<String, Budget>,
+        script: &Script,
+        estimated_ms: f64,
+    ) -> bool {
+        script.tags.iter().all(|tag| {
+            let Some(budget) = budgets.get(tag) else {
+                return true;
+            };
+
+            let runs = self.runs.get(tag).copied().unwrap_or_default();
+            let within_runs = budget.max_run_share.is_none_or(|max_share| {
+                (runs + 1) as f64 / (self.total_runs + 1) as f64 <= max_share
+            });
+
+            let runtime_ms = self.runtime_ms.get(tag).copied().unwrap_or_default();
+            let total_runtime_ms = self.total_runtime_ms + estimated_ms;
+            let within_time = budget.max_time_share.is_none_or(|max_share| {
+                total_runtime_ms <= 0.0
+                    || (runtime_ms + estimated_ms) / total_runtime_ms <= max_share
+            });
+
+            within_runs && within_time
+        })
+    }
+}
+
+#[test]
+fn test_tag_filter() {
+    let network = Script {
+        tags: vec!["network".to_string(), "slow".to_string()],
+        ..Script::new("a".to_string(), "ls".to_string())
+    };
+    let local = Script {
+        tags: vec!["local".to_string()],
+        ..Script::new("b".to_string(), "ls".to_string())
+    };
+
+    let filter = TagFilter {
+        tags: vec!["network".to_string()],
+        exclude_tags: vec![],
+    };
+    assert!(filter.matches(&network));
+    assert!(!filter.matches(&local));
+
+    let filter = TagFilter {
+        tags: vec![],
+        exclude_tags: vec!["slow".to_string()],
+    };
+    assert!(!filter.matches(&network));
+    assert!(filter.matches(&local));
+}
+
+#[test]
+fn test_budget_run_share() {
+    let budgets = BTreeMap::from([(
+        "network".to_string(),
+        Budget {
+            max_run_share: Some(0.2),
+            max_time_share: None,
+        },
+    )]);
+    let network = Script {
+        tags: vec!["network".to_string()],
+        ..Script::new("a".to_string(), "ls".to_string())
+    };
+    let local = Script {
+        tags: vec![],
+        ..Script::new("b".to_string(), "ls".to_string())
+    };
+    let mut usage = BudgetUsage::default();
+
+    assert!(!usage.allows(&budgets, &network, 0.0));
+    assert!(usage.allows(&budgets, &local, 0.0));
+
+    for _ in 0..4 {
+        usage.record(&local, 1.0);
+    }
+    assert!(usage.allows(&budgets, &network, 0.0));
+
+    usage.record(&network, 1.0);
+    assert!(!usage.allows(&budgets, &network, 0.0));
+}
+
+#[test]
+fn test_budget_time_share_cold_start() {
+    let budgets = BTreeMap::from([(
+        "slow".to_string(),
+        Budget {
+            max_run_share: None,
+            max_time_share: Some(0.5),
+        },
+    )]);
+    let slow = Script {
+        tags: vec!["slow".to_string()],
+        ..Script::new("a".to_string(), "ls".to_string())
+    };
+    let fast = Script {
+        tags: vec![],
+        ..Script::new("b".to_string(), "ls".to_string())
+    };
+    let mut usage = BudgetUsage::default();
+    usage.record(&fast, 10.0);
+
+    // An unmeasured script is charged its expected runtime rather than nothing.
+    assert!(usage.allows(&budgets, &slow, 10.0));
+    assert!(!usage.allows(&budgets, &slow, 1000.0));
+}
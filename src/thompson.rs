@@ -3,24 +3,31 @@ use rand::Rng;
 use serde::{Deserialize, Serialize};
 use std::os::raw::c_double;
 
+use crate::{
+    prior::Prior,
+    runtime::{CostModel, MIN_RUNTIME_MS},
+};
+
 extern "C" {
     /// the way I think of it is actually (a + 1, b + 1)
     fn boost_ibeta_inv(a: c_double, b: c_double, p: c_double) -> c_double;
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+#[serde(default)]
 pub struct ThompsonInfo {
     pub interesting: u64,
     pub uninteresting: u64,
 }
 
+/// Without a runtime, entries are scored as if they took a millisecond.
 pub fn skew_percentile(
     sampled_point: NotNan<f64>,
     runtime: &Option<NotNan<f64>>,
     user_bias: &NotNan<f64>,
 ) -> NotNan<f64> {
-    let mut time_scaler =
-        NotNan::new(100.0).unwrap() / runtime.unwrap_or(NotNan::new(0.01).unwrap());
+    let runtime = runtime.map_or(1.0, |runtime| f64::from(runtime).max(MIN_RUNTIME_MS));
+    let mut time_scaler = NotNan::new(100.0 / runtime).unwrap();
 
     // A script with bias of 5 is weighted to be equal to an equivalent script that runs 5x as fast.
     time_scaler *= user_bias;
@@ -28,22 +35,25 @@ pub fn skew_percentile(
     sampled_point * time_scaler
 }
 
-/// Prefer entries with low runtime.
-/// Entries without runtimes will always be run first.
+/// Prefer entries with low runtime, or whichever cost the models describe. The cost is
+/// sampled from each entry's model, so entries with uncertain costs are explored as well.
 pub fn thompson_sampling_bias_runtime(
     entries: &[&ThompsonInfo],
-    runtimes: &[&Option<NotNan<f64>>],
+    priors: &[Prior],
+    costs: &[Option<CostModel>],
     user_biases: &[&NotNan<f64>],
 ) -> Option<usize> {
-    // Handle uninitialized runtimes by assuming the fastest runtime.
+    let mut rng = rand::thread_rng();
     let mut selected_entry_index: Option<usize> = None;
     let mut selected_entry_percentile: NotNan<f64> = NotNan::new(-1.0).unwrap();
     for (index, entry) in entries.iter().enumerate() {
         let skewed_percentile = thompson_step_bias_runtime(
             entry.interesting,
             entry.uninteresting,
-            runtimes[index],
+            &priors[index],
+            &costs[index],
             user_biases[index],
+            &mut rng,
         );
 
         if skewed_percentile > selected_entry_percentile {
@@ -56,54 +66,65 @@ pub fn thompson_sampling_bias_runtime(
     selected_entry_index
 }
 
-/// Returns a vector mapping the nth selected entry to its index.
-///
-/// Ex. [0, 2, 1]: The first element was ranked first, the third second, and second third.
-pub fn thompson_ranking_bias_runtime(
+/// One runtime-skewed sample per entry, as used to rank them. Higher is better.
+pub fn thompson_scores_bias_runtime(
     entries: &[&ThompsonInfo],
-    runtimes: &[&Option<NotNan<f64>>],
+    priors: &[Prior],
+    costs: &[Option<CostModel>],
     user_biases: &[&NotNan<f64>],
-) -> Vec<usize> {
-    let mut percentiles = vec![NotNan::new(0.0).unwrap(); entries.len()];
-    for (index, entry) in entries.iter().enumerate() {
-        percentiles[index] = thompson_step_bias_runtime(
-            entry.interesting,
-            entry.uninteresting,
-            runtimes[index],
-            user_biases[index],
-        );
-    }
-
-    let mut sorted_percentile_index_mapping = percentiles.iter().enumerate().collect::<Vec<_>>();
-    sorted_percentile_index_mapping.sort_by_key(|&(_, percentile)| percentile);
-
-    sorted_percentile_index_mapping
+    rng: &mut impl Rng,
+) -> Vec<NotNan<f64>> {
+    entries
         .iter()
-        .rev()
-        .map(|(index, _percentile)| *index)
+        .enumerate()
+        .map(|(index, entry)| {
+            thompson_step_bias_runtime(
+                entry.interesting,
+                entry.uninteresting,
+                &priors[index],
+                &costs[index],
+                user_biases[index],
+                rng,
+            )
+        })
         .collect()
 }
 
+/// Returns a vector mapping the nth ranked entry to its index, from the highest score to the
+/// lowest.
+///
+/// Ex. [0, 2, 1]: The first element was ranked first, the third second, and second third.
+pub fn rank_by_score(scores: &[NotNan<f64>]) -> Vec<usize> {
+    let mut ranking = (0..scores.len()).collect::<Vec<_>>();
+    ranking.sort_by_key(|&index| scores[index]);
+    ranking.reverse();
+    ranking
+}
+
 fn thompson_step_bias_runtime(
     interesting: u64,
     uninteresting: u64,
-    runtime: &Option<NotNan<f64>>,
+    prior: &Prior,
+    cost: &Option<CostModel>,
     user_bias: &NotNan<f64>,
+    rng: &mut impl Rng,
 ) -> NotNan<f64> {
-    let mut rng = rand::thread_rng();
     // Random number from 0.0 to 1.0 inclusive
     let random_float = rng.gen_range(0.0..1.0);
 
     let percentile: f64;
     unsafe {
         percentile = boost_ibeta_inv(
-            (interesting + 1) as f64,
-            (uninteresting + 1) as f64,
+            interesting as f64 + prior.alpha,
+            uninteresting as f64 + prior.beta,
             random_float,
         );
     }
 
-    let skewed_percentile = skew_percentile(NotNan::new(percentile).unwrap(), runtime, user_bias);
+    let runtime = cost
+        .as_ref()
+        .map(|model| NotNan::new(model.sample(rng)).unwrap());
+    let skewed_percentile = skew_percentile(NotNan::new(percentile).unwrap(), &runtime, user_bias);
 
     // println!(
     //     "Total percentage of area at point {:.4}: {:.2}% B({}, {}) Skewed area: {:.2}",
@@ -117,11 +138,21 @@ fn thompson_step_bias_runtime(
     skewed_percentile
 }
 
-pub fn thompson_sampling(entries: &[&ThompsonInfo], user_biases: &[&NotNan<f64>]) -> Option<usize> {
+pub fn thompson_sampling(
+    entries: &[&ThompsonInfo],
+    priors: &[Prior],
+    user_biases: &[&NotNan<f64>],
+) -> Option<usize> {
+    let mut rng = rand::thread_rng();
     let mut selected_entry_index: Option<usize> = None;
     let mut selected_entry_percentile: f64 = -1.0;
     for (index, entry) in entries.iter().enumerate() {
-        let mut percentile = thompson_step(entry.interesting, entry.uninteresting);
+        let mut percentile = thompson_step(
+            entry.interesting,
+            entry.uninteresting,
+            &priors[index],
+            &mut rng,
+        );
         // println!(
         //     "Total percentage of area at point {:.4}: {:.2}%",
         //     percentile,
@@ -137,39 +168,36 @@ pub fn thompson_sampling(entries: &[&ThompsonInfo], user_biases: &[&NotNan<f64>]
     selected_entry_index
 }
 
-/// Returns a vector mapping the nth selected entry to its index.
-///
-/// Ex. [0, 2, 1]: The first element was ranked first, the third second, and second third.
-pub fn thompson_ranking(entries: &[&ThompsonInfo]) -> Vec<usize> {
-    let mut percentiles = vec![0.0; entries.len()];
-    for (idx, entry) in entries.iter().enumerate() {
-        percentiles[idx] = thompson_step(entry.interesting, entry.uninteresting);
-    }
-
-    let mut sorted_percentile_index_mapping = percentiles
-        .iter()
-        .map(|x| NotNan::new(*x).unwrap())
-        .enumerate()
-        .collect::<Vec<_>>();
-    sorted_percentile_index_mapping.sort_by_key(|&(_, percentile)| percentile);
-
-    sorted_percentile_index_mapping
+/// One sample of the failure rate per entry, as used to rank them. Higher is better.
+pub fn thompson_scores(
+    entries: &[&ThompsonInfo],
+    priors: &[Prior],
+    rng: &mut impl Rng,
+) -> Vec<NotNan<f64>> {
+    entries
         .iter()
-        .rev()
-        .map(|(idx, _percentile)| *idx)
+        .zip(priors)
+        .map(|(entry, prior)| {
+            NotNan::new(thompson_step(
+                entry.interesting,
+                entry.uninteresting,
+                prior,
+                rng,
+            ))
+            .unwrap()
+        })
         .collect()
 }
 
-fn thompson_step(interesting: u64, uninteresting: u64) -> f64 {
-    let mut rng = rand::thread_rng();
+fn thompson_step(interesting: u64, uninteresting: u64, prior: &Prior, rng: &mut impl Rng) -> f64 {
     // Random number from 0.0 to 1.0 inclusive
     let random_float: f64 = rng.gen_range(0.0..1.0);
     // println!("Percentile to sample: {}", random_float);
     let percentile: f64;
     unsafe {
         percentile = boost_ibeta_inv(
-            (interesting + 1) as f64,
-            (uninteresting + 1) as f64,
+            interesting as f64 + prior.alpha,
+            uninteresting as f64 + prior.beta,
             random_float,
         );
     }
@@ -183,22 +211,65 @@ fn thompson_step(interesting: u64, uninteresting: u64) -> f64 {
     percentile
 }
 
-/// Returns the 50th percentile of the beta distribution.
-pub fn dist_area_at_percentile(entry: &ThompsonInfo, area: f64) -> f64 {
+/// Returns the point below which `area` of the posterior beta distribution lies.
+pub fn dist_area_at_percentile(entry: &ThompsonInfo, prior: &Prior, area: f64) -> f64 {
     let point: f64;
     unsafe {
         point = boost_ibeta_inv(
-            (entry.interesting + 1) as f64,
-            (entry.uninteresting + 1) as f64,
+            entry.interesting as f64 + prior.alpha,
+            entry.uninteresting as f64 + prior.beta,
             area,
         );
     }
     point
 }
 
+/// The posterior over an entry's failure rate, summarized.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PosteriorSummary {
+    pub mean: f64,
+    pub p5: f64,
+    pub median: f64,
+    pub p95: f64,
+}
+
+/// Wall-clock time to the next interesting case, with a 90% credible interval.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeToNext {
+    pub expected_ms: f64,
+    pub low_ms: f64,
+    /// Long for scripts that haven't found anything yet, as their 5th percentile is close to 0.
+    pub high_ms: f64,
+}
+
+impl PosteriorSummary {
+    pub fn new(entry: &ThompsonInfo, prior: &Prior) -> PosteriorSummary {
+        let alpha = entry.interesting as f64 + prior.alpha;
+        let beta = entry.uninteresting as f64 + prior.beta;
+
+        PosteriorSummary {
+            mean: alpha / (alpha + beta),
+            p5: dist_area_at_percentile(entry, prior, 0.05),
+            median: dist_area_at_percentile(entry, prior, 0.5),
+            p95: dist_area_at_percentile(entry, prior, 0.95),
+        }
+    }
+
+    /// Time to the next interesting case for runs taking `runtime_ms`: the runtime divided by
+    /// the mean failure rate. The interval comes from the 95th and 5th percentiles of the
+    /// failure rate.
+    pub fn time_to_next(&self, runtime_ms: f64) -> TimeToNext {
+        TimeToNext {
+            expected_ms: runtime_ms / self.mean,
+            low_ms: runtime_ms / self.p95,
+            high_ms: runtime_ms / self.p5,
+        }
+    }
+}
+
 #[test]
 fn test_thompson_sampling_none() {
-    assert_eq!(thompson_sampling(&vec![], &vec![]), None);
+    assert_eq!(thompson_sampling(&[], &[], &[]), None);
 }
 
 #[test]
@@ -209,6 +280,7 @@ fn test_thompson_sampling_one() {
                 interesting: 0,
                 uninteresting: 0
             }],
+            &[Prior::default()],
             &[&NotNan::new(1.0).unwrap(), &NotNan::new(1.0).unwrap()]
         ),
         Some(0)
@@ -229,6 +301,7 @@ fn test_thompson_sampling_prefer_interesting() {
                     uninteresting: 0
                 }
             ],
+            &[Prior::default(); 2],
             &[&NotNan::new(1.0).unwrap(), &NotNan::new(1.0).unwrap()]
         ),
         Some(1)
@@ -249,9 +322,24 @@ fn test_thompson_sampling_bias_prefer_fast() {
                     uninteresting: 100
                 }
             ],
+            &[Prior::default(); 2],
             &[
-                &Some(NotNan::new(1.0).unwrap()),
-                &Some(NotNan::new(100.0).unwrap())
+                Some(
+                    crate::runtime::RuntimeModel {
+                        samples: 10,
+                        log_mean: 1f64.ln(),
+                        log_m2: 0.0,
+                    }
+                    .into(),
+                ),
+                Some(
+                    crate::runtime::RuntimeModel {
+                        samples: 10,
+                        log_mean: 100f64.ln(),
+                        log_m2: 0.0,
+                    }
+                    .into(),
+                )
             ],
             &[&NotNan::new(1.0).unwrap(), &NotNan::new(1.0).unwrap()]
         ),
@@ -260,7 +348,9 @@ fn test_thompson_sampling_bias_prefer_fast() {
 }
 
 #[test]
-fn test_thompson_sampling_bias_prefer_unknown() {
+fn test_thompson_sampling_bias_prefer_cheap_cold_start() {
+    // The unrun script is only expected to be cheap, which is enough to try it before a slow
+    // script that keeps failing.
     assert_eq!(
         thompson_sampling_bias_runtime(
             &[
@@ -273,9 +363,181 @@ fn test_thompson_sampling_bias_prefer_unknown() {
                     uninteresting: 0
                 }
             ],
-            &[&Some(NotNan::new(1.0).unwrap()), &None],
+            &[Prior::default(); 2],
+            &[
+                Some(
+                    crate::runtime::RuntimeModel {
+                        samples: 10,
+                        log_mean: 1000f64.ln(),
+                        log_m2: 0.0,
+                    }
+                    .into(),
+                ),
+                Some(crate::runtime::RuntimeModel::expected(0.1).into())
+            ],
             &[&NotNan::new(1.0).unwrap(), &NotNan::new(1.0).unwrap()]
         ),
         Some(1)
     );
 }
+
+#[test]
+fn test_thompson_sampling_bias_zero_runtime() {
+    // Sub-microsecond scripts score as if they took a microsecond rather than dividing by zero.
+    assert_eq!(
+        skew_percentile(
+            NotNan::new(0.5).unwrap(),
+            &Some(NotNan::new(0.0).unwrap()),
+            &NotNan::new(1.0).unwrap()
+        ),
+        NotNan::new(0.5 * 100.0 / MIN_RUNTIME_MS).unwrap()
+    );
+
+    let selected = thompson_sampling_bias_runtime(
+        &[
+            &ThompsonInfo {
+                interesting: 0,
+                uninteresting: 10,
+            },
+            &ThompsonInfo {
+                interesting: 0,
+                uninteresting: 10,
+            },
+        ],
+        &[Prior::default(); 2],
+        &[
+            Some(
+                crate::runtime::RuntimeModel {
+                    samples: 10,
+                    log_mean: MIN_RUNTIME_MS.ln(),
+                    log_m2: 0.0,
+                }
+                .into(),
+            ),
+            Some(
+                crate::runtime::RuntimeModel {
+                    samples: 10,
+                    log_mean: MIN_RUNTIME_MS.ln(),
+                    log_m2: 0.0,
+                }
+                .into(),
+            ),
+        ],
+        &[&NotNan::new(1.0).unwrap(), &NotNan::new(1.0).unwrap()],
+    );
+    assert!(selected.is_some());
+}
+
+#[test]
+fn test_thompson_sampling_pessimistic_prior() {
+    assert_eq!(
+        thompson_sampling(
+            &[
+                &ThompsonInfo {
+                    interesting: 0,
+                    uninteresting: 0
+                },
+                &ThompsonInfo {
+                    interesting: 50,
+                    uninteresting: 50
+                }
+            ],
+            &[
+                Prior {
+                    alpha: 0.1,
+                    beta: 1000.0
+                },
+                Prior::default()
+            ],
+            &[&NotNan::new(1.0).unwrap(), &NotNan::new(1.0).unwrap()]
+        ),
+        Some(1)
+    );
+}
+
+#[test]
+fn test_thompson_sampling_bias_explores_uncertain_runtime() {
+    // Measured once at 50ms, the second entry might well be faster than the first, which is
+    // reliably 20ms. It should win some of the time, but not most of the time.
+    let selected_uncertain = (0..1000)
+        .filter(|_| {
+            thompson_sampling_bias_runtime(
+                &[
+                    &ThompsonInfo {
+                        interesting: 1000,
+                        uninteresting: 1000,
+                    },
+                    &ThompsonInfo {
+                        interesting: 1000,
+                        uninteresting: 1000,
+                    },
+                ],
+                &[Prior::default(); 2],
+                &[
+                    Some(
+                        crate::runtime::RuntimeModel {
+                            samples: 100,
+                            log_mean: 20f64.ln(),
+                            log_m2: 0.0,
+                        }
+                        .into(),
+                    ),
+                    Some(
+                        crate::runtime::RuntimeModel {
+                            samples: 1,
+                            log_mean: 50f64.ln(),
+                            log_m2: 0.0,
+                        }
+                        .into(),
+                    ),
+                ],
+                &[&NotNan::new(1.0).unwrap(), &NotNan::new(1.0).unwrap()],
+            ) == Some(1)
+        })
+        .count();
+
+    assert!(
+        selected_uncertain > 50 && selected_uncertain < 500,
+        "{}",
+        selected_uncertain
+    );
+}
+
+#[test]
+fn test_rank_by_score() {
+    let scores = [0.2, 0.7, 0.1]
+        .into_iter()
+        .map(|score| NotNan::new(score).unwrap())
+        .collect::<Vec<_>>();
+
+    assert_eq!(rank_by_score(&scores), vec![1, 0, 2]);
+}
+
+#[test]
+fn test_posterior_summary() {
+    let entry = ThompsonInfo {
+        interesting: 9,
+        uninteresting: 89,
+    };
+    let summary = PosteriorSummary::new(&entry, &Prior::default());
+
+    assert!((summary.mean - 0.1).abs() < 1e-9);
+    assert!(summary.p5 < summary.median && summary.median < summary.p95);
+
+    // A mean failure rate of 0.1 means 10 runs per interesting case.
+    let time = summary.time_to_next(50.0);
+    assert!((time.expected_ms - 500.0).abs() < 1e-6);
+    assert!(time.low_ms < time.expected_ms && time.expected_ms < time.high_ms);
+
+    // Scripts that haven't found anything yet still get a finite estimate and interval.
+    let none = PosteriorSummary::new(
+        &ThompsonInfo {
+            interesting: 0,
+            uninteresting: 20,
+        },
+        &Prior::default(),
+    );
+    let time = none.time_to_next(50.0);
+    assert!((time.expected_ms - 1100.0).abs() < 1e-6);
+    assert!(time.high_ms.is_finite() && time.expected_ms < time.high_ms);
+}
@@ -27,13 +27,45 @@ fn simple_2_scripts() -> Result<(), Box<dyn std::error::Error>> {
 
     cmd.assert().success();
 
+    assert_simple_2_scripts_ran("./tests/temp/temp.json", 2)?;
+
     let mut cmd = Command::cargo_bin("bts")?;
 
     cmd.arg("summarize").arg("./tests/temp/temp.json");
     cmd.assert()
         .success()
-        .stdout(predicate::str::contains("50th percentile: 0.2929"))
-        .stdout(predicate::str::contains("50th percentile: 0.7071"));
+        .stdout(predicate::str::contains("50th percentile: "))
+        .stdout(predicate::str::contains("Runs: "));
+
+    Ok(())
+}
+
+/// Which of the two scripts runs is up to the sampling, so only check the totals: `steps`
+/// runs, with `exit 1` always interesting and `exit 0` never.
+fn assert_simple_2_scripts_ran(path: &str, steps: u64) -> Result<(), Box<dyn std::error::Error>> {
+    let config: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    let scripts = config["scripts"].as_array().unwrap();
+    let count = |index: usize, field: &str| scripts[index]["results"][field].as_u64().unwrap_or(0);
+
+    assert_eq!(scripts[0]["name"], "exit 1");
+    assert_eq!(scripts[1]["name"], "exit 0");
+    assert_eq!(
+        scripts
+            .iter()
+            .map(|script| script["runcount"].as_u64().unwrap())
+            .sum::<u64>(),
+        steps
+    );
+    assert_eq!(
+        count(0, "interesting"),
+        scripts[0]["runcount"].as_u64().unwrap()
+    );
+    assert_eq!(count(0, "uninteresting"), 0);
+    assert_eq!(count(1, "interesting"), 0);
+    assert_eq!(
+        count(1, "uninteresting"),
+        scripts[1]["runcount"].as_u64().unwrap()
+    );
 
     Ok(())
 }
@@ -168,3 +200,1171 @@ fn lint_zero_limit() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn invalid_prior_is_rejected_on_load() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("summarize").arg("./tests/lint/prior-zero.json");
+
+    cmd.assert().failure().stdout(predicate::str::contains(
+        "ERROR: ./tests/lint/prior-zero.json: Test 1 prior_alpha must be a finite number greater than 0.",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn new_splits_on_first_equals() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("new")
+        .arg("./tests/temp/new-equals.json")
+        .arg("-t")
+        .arg("level=./scripts/exit.sh --level=3")
+        .arg("--bias")
+        .arg("level=2")
+        .arg("--limit")
+        .arg("level=5")
+        .arg("--timeout-ms")
+        .arg("level=1000");
+
+    cmd.assert().success();
+
+    let config = std::fs::read_to_string("./tests/temp/new-equals.json")?;
+    assert!(config.contains(r#""command": "./scripts/exit.sh --level=3""#));
+    assert!(config.contains(r#""bias": 2.0"#));
+    assert!(config.contains(r#""limit": 5"#));
+    assert!(config.contains(r#""timeout_ms": 1000"#));
+
+    Ok(())
+}
+
+#[test]
+fn new_unknown_mapping_name() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("new")
+        .arg("./tests/temp/new-unknown.json")
+        .arg("-t")
+        .arg("a=ls")
+        .arg("--bias")
+        .arg("b=2");
+
+    cmd.assert().failure().stdout(predicate::str::contains(
+        "ERROR: --bias refers to unknown test b",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn new_from_csv() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("new")
+        .arg("./tests/temp/new-csv.json")
+        .arg("--from")
+        .arg("./tests/new/commands.csv");
+
+    cmd.assert().success();
+
+    let config = std::fs::read_to_string("./tests/temp/new-csv.json")?;
+    assert!(config.contains(r#""name": "exit, 1""#));
+    assert!(config.contains(r#""bias": 2.5"#));
+    assert!(config.contains(r#""limit": 3"#));
+    assert!(config.contains(r#""timeout_ms": 1000"#));
+
+    Ok(())
+}
+
+#[test]
+fn new_from_text() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("new")
+        .arg("./tests/temp/new-text.json")
+        .arg("--from")
+        .arg("./tests/new/commands.txt");
+
+    cmd.assert().success();
+
+    let config = std::fs::read_to_string("./tests/temp/new-text.json")?;
+    assert!(config.contains(r#""name": "./scripts/exit.sh 1""#));
+    assert!(!config.contains("One command per line"));
+
+    Ok(())
+}
+
+#[test]
+fn generator_matrix() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("run")
+        .arg("./config-tests/generator-matrix.json")
+        .arg("--steps")
+        .arg("5")
+        .arg("--output")
+        .arg("./tests/temp/generator-matrix.json");
+
+    cmd.assert().success();
+
+    let config = std::fs::read_to_string("./tests/temp/generator-matrix.json")?;
+    assert!(config.contains(r#""name": "test 0.1""#));
+    assert!(config.contains(r#""command": "./scripts/bandit_example.sh 0.9 0""#));
+    assert!(config.contains(r#""generator": "test {p}""#));
+
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("summarize")
+        .arg("./tests/temp/generator-matrix.json");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("test 0.9"))
+        .stdout(predicate::str::contains("exit 0"));
+
+    Ok(())
+}
+
+#[test]
+fn generator_glob_relative_to_config() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("run")
+        .arg("./config-tests/generator-glob.json")
+        .arg("--steps")
+        .arg("1")
+        .arg("--output")
+        .arg("./tests/temp/generator-glob.json");
+
+    cmd.assert().success();
+
+    let config = std::fs::read_to_string("./tests/temp/generator-glob.json")?;
+    assert!(config.contains(r#""name": "glob exit""#));
+    assert!(config.contains(r#""command": "config-tests/../scripts/exit.sh 1""#));
+    assert!(config.contains(r#""runcount": 1"#));
+
+    Ok(())
+}
+
+#[test]
+fn import_cargo_test_list() -> Result<(), Box<dyn std::error::Error>> {
+    let _ = std::fs::remove_file("./tests/temp/cargo-import.json");
+
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("import")
+        .arg("cargo-test")
+        .arg("./tests/temp/cargo-import.json")
+        .arg("--list")
+        .arg("./tests/import/cargo-test-list.txt");
+
+    cmd.assert().success().stdout(predicate::str::contains(
+        "Added 2 tests, retired 0 tests, kept 0 tests.",
+    ));
+
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("import")
+        .arg("cargo-test")
+        .arg("./tests/temp/cargo-import.json")
+        .arg("--list")
+        .arg("./tests/import/cargo-test-list-updated.txt");
+
+    cmd.assert().success().stdout(predicate::str::contains(
+        "Added 1 tests, retired 1 tests, kept 1 tests.",
+    ));
+
+    let config = std::fs::read_to_string("./tests/temp/cargo-import.json")?;
+    assert!(config
+        .contains(r#""command": "cargo test -- --exact thompson::test_thompson_sampling_one""#));
+    assert!(config.contains(r#""retired": "No longer listed by cargo test""#));
+    assert!(!config.contains("basic_benchmark"));
+
+    Ok(())
+}
+
+#[test]
+fn migrate_legacy_config() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("migrate")
+        .arg("./tests/migrate/legacy.json")
+        .arg("--output")
+        .arg("./tests/temp/migrated.json");
+
+    cmd.assert().success().stdout(predicate::str::contains(
+        "Migrated config from version 0 to version 1.",
+    ));
+
+    let config = std::fs::read_to_string("./tests/temp/migrated.json")?;
+    assert!(config.contains(r#""version": 1"#));
+    assert!(config.contains(r#""runcount": 5"#));
+
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("migrate").arg("./tests/temp/migrated.json");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Config is already at version 1."));
+
+    Ok(())
+}
+
+#[test]
+fn refuse_newer_config() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("lint").arg("./tests/migrate/future.json");
+
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("Config version 999 is newer than the newest version this build of bts supports (1). Upgrade bts to use this config."));
+
+    Ok(())
+}
+
+#[test]
+fn toml_and_yaml_configs() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("run")
+        .arg("./config-tests/simple-2-scripts.toml")
+        .arg("--steps")
+        .arg("2")
+        .arg("--output")
+        .arg("./tests/temp/simple-2-scripts.yaml");
+
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("convert")
+        .arg("./tests/temp/simple-2-scripts.yaml")
+        .arg("./tests/temp/simple-2-scripts-converted.json");
+
+    cmd.assert().success();
+
+    assert_simple_2_scripts_ran("./tests/temp/simple-2-scripts-converted.json", 2)?;
+
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("summarize")
+        .arg("./tests/temp/simple-2-scripts-converted.json");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("50th percentile: "))
+        .stdout(predicate::str::contains("Runs: "));
+
+    Ok(())
+}
+
+#[test]
+fn include_saves_state_back() -> Result<(), Box<dyn std::error::Error>> {
+    let runcount = |path: &str| -> Result<u64, Box<dyn std::error::Error>> {
+        let config: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        Ok(config["scripts"][0]["runcount"].as_u64().unwrap_or(0))
+    };
+    std::fs::remove_dir_all("./tests/temp/include").ok();
+
+    // Saving elsewhere writes the included file next to the output, not over the original.
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("run")
+        .arg("./tests/include/root.json")
+        .arg("--steps")
+        .arg("2")
+        .arg("--output")
+        .arg("./tests/temp/include/root.json");
+
+    cmd.assert().success();
+
+    let root: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string("./tests/temp/include/root.json")?)?;
+    assert_eq!(root["include"][0], "team.json");
+    assert_eq!(root["scripts"].as_array().unwrap().len(), 1);
+    assert_eq!(root["scripts"][0]["name"], "exit 1");
+    assert!(root["scripts"][0].get("bias").is_none());
+    assert!(root["scripts"][0].get("env").is_none());
+
+    let team = std::fs::read_to_string("./tests/temp/include/team.json")?;
+    assert!(team.contains(r#""bias": 3.0"#));
+    assert_eq!(team.matches("timeout_ms").count(), 1);
+    assert_eq!(
+        runcount("./tests/temp/include/root.json")? + runcount("./tests/temp/include/team.json")?,
+        2
+    );
+    assert_eq!(runcount("./tests/include/team.json")?, 0);
+
+    // Saving in place writes the included file back.
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("run")
+        .arg("./tests/temp/include/root.json")
+        .arg("--steps")
+        .arg("2")
+        .arg("--output")
+        .arg("./tests/temp/include/root.json");
+
+    cmd.assert().success();
+
+    assert_eq!(
+        runcount("./tests/temp/include/root.json")? + runcount("./tests/temp/include/team.json")?,
+        4
+    );
+
+    Ok(())
+}
+
+#[test]
+fn lint_include_collision() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("lint").arg("./tests/include/collision.json");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("exit 0 ERROR: Duplicate script name in ./tests/include/collision.json and ./tests/include/team.json."));
+
+    Ok(())
+}
+
+fn runcounts(path: &str) -> Result<Vec<(String, u64)>, Box<dyn std::error::Error>> {
+    let config: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    Ok(config["scripts"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|script| {
+            (
+                script["name"].as_str().unwrap().to_string(),
+                script["runcount"].as_u64().unwrap(),
+            )
+        })
+        .collect())
+}
+
+#[test]
+fn tag_budget_limits_share() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("run")
+        .arg("./config-tests/tags-budget.json")
+        .arg("--steps")
+        .arg("10")
+        .arg("--output")
+        .arg("./tests/temp/tags-budget.json");
+
+    cmd.assert().success();
+
+    let runcounts = runcounts("./tests/temp/tags-budget.json")?;
+    assert_eq!(runcounts[0].0, "network");
+    assert!(runcounts[0].1 <= 2);
+    assert_eq!(runcounts[0].1 + runcounts[1].1, 10);
+
+    Ok(())
+}
+
+#[test]
+fn tag_budget_runs_when_nothing_else_can() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("run")
+        .arg("./config-tests/tags-budget.json")
+        .arg("--steps")
+        .arg("3")
+        .arg("--tag")
+        .arg("network")
+        .arg("--output")
+        .arg("./tests/temp/tags-budget-only.json");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("No scripts left").not());
+
+    let runcounts = runcounts("./tests/temp/tags-budget-only.json")?;
+    assert_eq!(runcounts[0], ("network".to_string(), 3));
+    assert_eq!(runcounts[1], ("local".to_string(), 0));
+
+    Ok(())
+}
+
+#[test]
+fn tag_filters() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("run")
+        .arg("./config-tests/tags-budget.json")
+        .arg("--steps")
+        .arg("3")
+        .arg("--exclude-tag")
+        .arg("network")
+        .arg("--output")
+        .arg("./tests/temp/tags-filter.json");
+
+    cmd.assert().success();
+
+    let runcounts = runcounts("./tests/temp/tags-filter.json")?;
+    assert_eq!(runcounts[0], ("network".to_string(), 0));
+    assert_eq!(runcounts[1], ("local".to_string(), 3));
+
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("rank")
+        .arg("./tests/temp/tags-filter.json")
+        .arg("--tag")
+        .arg("local");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("local"))
+        .stdout(predicate::str::contains("network").not());
+
+    Ok(())
+}
+
+#[test]
+fn custom_prior_in_summary() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("summarize").arg("./config-tests/custom-prior.json");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("50th percentile: 0.2063"));
+
+    Ok(())
+}
+
+#[test]
+fn cold_start_uses_expected_runtime() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("run")
+        .arg("./config-tests/cold-start.json")
+        .arg("--steps")
+        .arg("5")
+        .arg("--output")
+        .arg("./tests/temp/cold-start.json");
+
+    cmd.assert().success();
+
+    // A script measured at 0ms keeps being scored, and the unmeasured script expected to take
+    // 100s doesn't jump the queue.
+    assert_eq!(
+        runcounts("./tests/temp/cold-start.json")?,
+        vec![
+            ("instant".to_string(), 10),
+            ("slow to start".to_string(), 0)
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn cold_start_prefers_script_expected_runtime() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("run")
+        .arg("./config-tests/cold-start-expected.json")
+        .arg("--steps")
+        .arg("1")
+        .arg("--output")
+        .arg("./tests/temp/cold-start-expected.json");
+
+    cmd.assert().success();
+
+    // The script's own estimate beats the config's, so it runs first.
+    assert_eq!(
+        runcounts("./tests/temp/cold-start-expected.json")?,
+        vec![
+            ("unknown".to_string(), 0),
+            ("expected cheap".to_string(), 1)
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn zero_duration_runs() -> Result<(), Box<dyn std::error::Error>> {
+    std::fs::copy(
+        "./config-tests/simple-2-scripts.json",
+        "./tests/temp/zero-duration.json",
+    )?;
+    for _ in 0..2 {
+        let mut cmd = Command::cargo_bin("bts")?;
+        cmd.arg("record")
+            .arg("./tests/temp/zero-duration.json")
+            .arg("exit 0")
+            .arg("--uninteresting")
+            .arg("--runtime-ms")
+            .arg("0");
+        cmd.assert().success();
+    }
+
+    let config: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string("./tests/temp/zero-duration.json")?)?;
+    assert_eq!(config["scripts"][1]["avgruntime_ms"], 0.0);
+
+    // Scoring a script that took no time doesn't divide by zero.
+    let mut cmd = Command::cargo_bin("bts")?;
+    cmd.arg("summarize").arg("./tests/temp/zero-duration.json");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Typical runtime: 0.00ms"))
+        .stdout(predicate::str::contains("inf").not());
+
+    let mut cmd = Command::cargo_bin("bts")?;
+    cmd.arg("run")
+        .arg("./tests/temp/zero-duration.json")
+        .arg("--steps")
+        .arg("2")
+        .arg("--output")
+        .arg("./tests/temp/zero-duration.json");
+    cmd.assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn run_records_resource_usage() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("run")
+        .arg("./config-tests/simple-2-scripts.json")
+        .arg("--steps")
+        .arg("2")
+        .arg("--output")
+        .arg("./tests/temp/resources.json");
+
+    cmd.assert().success();
+
+    let config: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string("./tests/temp/resources.json")?)?;
+    for script in config["scripts"].as_array().unwrap() {
+        if script["runcount"].as_u64().unwrap() > 0 {
+            assert_eq!(script["cpu_time"]["samples"], script["runcount"]);
+            assert!(script["max_rss_kb"].as_u64().unwrap() > 0);
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn cpu_limit_breach() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("run")
+        .arg("./config-tests/cpu-limit.json")
+        .arg("--steps")
+        .arg("1")
+        .arg("--output")
+        .arg("./tests/temp/cpu-limit.json");
+
+    cmd.assert().success().stdout(predicate::str::contains(
+        "Command exceeded its cpu_seconds limit",
+    ));
+
+    let config: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string("./tests/temp/cpu-limit.json")?)?;
+    let script = &config["scripts"][0];
+    assert_eq!(script["limit_breaches"]["cpu_seconds"], 1);
+    assert_eq!(script["results"]["interesting"], 0);
+    assert_eq!(script["results"]["uninteresting"], 0);
+
+    Ok(())
+}
+
+#[test]
+fn memory_limit_breach() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("run")
+        .arg("./config-tests/memory-limit.json")
+        .arg("--steps")
+        .arg("1")
+        .arg("--output")
+        .arg("./tests/temp/memory-limit.json");
+
+    // The script exits with an error once an allocation fails, without any signal.
+    cmd.assert().success().stdout(predicate::str::contains(
+        "Command exceeded its address_space_mb limit",
+    ));
+
+    let config: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string("./tests/temp/memory-limit.json")?)?;
+    let script = &config["scripts"][0];
+    assert_eq!(script["limit_breaches"]["address_space_mb"], 1);
+    assert_eq!(script["results"]["interesting"], 0);
+    assert_eq!(script["results"]["uninteresting"], 0);
+
+    Ok(())
+}
+
+#[test]
+fn timeout_kills_child_processes() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("run")
+        .arg("./config-tests/timeout.json")
+        .arg("--steps")
+        .arg("1")
+        .arg("--output")
+        .arg("./tests/temp/timeout.json");
+
+    let start = std::time::Instant::now();
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Command timed out after 200ms"));
+    // The sleep the script started is killed with it instead of holding its output open.
+    assert!(start.elapsed() < std::time::Duration::from_secs(10));
+
+    let config: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string("./tests/temp/timeout.json")?)?;
+    let script = &config["scripts"][0];
+    assert_eq!(script["outcomes"]["timeout"], 1);
+    assert_eq!(script["results"]["interesting"], 0);
+    assert_eq!(script["results"]["uninteresting"], 0);
+    assert_eq!(script["runcount"], 1);
+    // The run only says the script takes at least 200ms, so it isn't averaged in either.
+    assert!(script["avgruntime_ms"].is_null());
+    assert!(script.get("runtime").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn outcomes_by_signal() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("run")
+        .arg("./config-tests/signals.json")
+        .arg("--steps")
+        .arg("6")
+        .arg("--output")
+        .arg("./tests/temp/signals.json");
+
+    cmd.assert().success();
+
+    let config: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string("./tests/temp/signals.json")?)?;
+    let segfault = &config["scripts"][0];
+    let killed = &config["scripts"][1];
+    assert_eq!(segfault["outcomes"]["SIGSEGV"], segfault["runcount"]);
+    assert_eq!(segfault["results"]["interesting"], segfault["runcount"]);
+    assert_eq!(killed["outcomes"]["SIGKILL"], killed["runcount"]);
+    assert_eq!(killed["results"]["interesting"], 0);
+    assert_eq!(killed["results"]["uninteresting"], 0);
+    // The oracle is inherited from the defaults, so it isn't saved with the scripts.
+    assert!(segfault.get("oracle").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn known_signatures_are_uninteresting() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("run")
+        .arg("./config-tests/signatures.json")
+        .arg("--steps")
+        .arg("4")
+        .arg("--output")
+        .arg("./tests/temp/signatures.json");
+
+    cmd.assert().success();
+
+    let config: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string("./tests/temp/signatures.json")?)?;
+    let script = &config["scripts"][0];
+    assert_eq!(script["runcount"], 4);
+    assert_eq!(script["results"]["interesting"], 1);
+    assert_eq!(script["results"]["uninteresting"], 3);
+    assert_eq!(script["signatures"]["src/parse.rs:10"], 4);
+    assert_eq!(config["signatures"]["src/parse.rs:10"]["count"], 4);
+    assert_eq!(
+        config["signatures"]["src/parse.rs:10"]["first_seen_by"],
+        "same-bug"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn stop_rules() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("run")
+        .arg("./config-tests/stop-rules.json")
+        .arg("--steps")
+        .arg("10")
+        .arg("--output")
+        .arg("./tests/temp/stop-rules.json");
+
+    cmd.assert().success();
+
+    let config: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string("./tests/temp/stop-rules.json")?)?;
+    assert_eq!(config["scripts"][0]["runcount"], 1);
+    assert_eq!(config["scripts"][1]["runcount"], 2);
+    assert_eq!(config["scripts"][2]["runcount"], 7);
+
+    let mut cmd = Command::cargo_bin("bts")?;
+    cmd.arg("summarize").arg("./tests/temp/stop-rules.json");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "- limited: Found 1 interesting results (limit 1)",
+        ))
+        .stdout(predicate::str::contains(
+            "- few runs: Ran 2 times (max_runs 2)",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn rank_skips_scripts_at_their_limit() -> Result<(), Box<dyn std::error::Error>> {
+    for ignore_runtime in [false, true] {
+        let mut cmd = Command::cargo_bin("bts")?;
+        cmd.arg("rank").arg("./config-tests/limit-reached.json");
+        if ignore_runtime {
+            cmd.arg("-i");
+        }
+
+        let output = cmd.assert().success().get_output().stdout.clone();
+        let mut ranking = String::from_utf8(output)?
+            .lines()
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        ranking.sort();
+        assert_eq!(ranking, vec!["exit 0", "exit 2"]);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn run_skips_scripts_at_their_limit() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("run")
+        .arg("./config-tests/limit-reached.json")
+        .arg("--steps")
+        .arg("6")
+        .arg("--output")
+        .arg("./tests/temp/limit-reached.json");
+
+    cmd.assert().success();
+
+    let config: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string("./tests/temp/limit-reached.json")?)?;
+    assert_eq!(config["scripts"][0]["runcount"], 1);
+    assert_eq!(config["scripts"][0]["results"]["interesting"], 1);
+    let active_runs = config["scripts"][1]["runcount"].as_u64().unwrap()
+        + config["scripts"][2]["runcount"].as_u64().unwrap();
+    assert_eq!(active_runs, 6);
+
+    let mut cmd = Command::cargo_bin("bts")?;
+    cmd.arg("rank")
+        .arg("-i")
+        .arg("-v")
+        .arg("./tests/temp/limit-reached.json");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("limited").not());
+
+    Ok(())
+}
+
+#[test]
+fn rank_json_format() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+    cmd.arg("rank")
+        .arg("./config-tests/limit-reached.json")
+        .arg("--format")
+        .arg("json");
+
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let rows: serde_json::Value = serde_json::from_slice(&output)?;
+    let rows = rows.as_array().unwrap();
+    assert_eq!(rows.len(), 2);
+    for (index, row) in rows.iter().enumerate() {
+        assert_eq!(row["rank"], index + 1);
+        assert_ne!(row["name"], "limited");
+        assert_eq!(row["posterior_mean"], 0.5);
+        assert_eq!(row["runs"], 0);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn summarize_csv_format() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+    cmd.arg("summarize")
+        .arg("./config-tests/limit-reached.json")
+        .arg("--format")
+        .arg("csv");
+
+    let output = String::from_utf8(cmd.assert().success().get_output().stdout.clone())?;
+    let lines = output.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].starts_with("name,rank,posterior_mean,"));
+    assert!(lines[0].ends_with(",status"));
+    assert!(lines[1..3]
+        .iter()
+        .all(|line| line.starts_with("exit ") && line.ends_with(",Active")));
+    // The script that reached its limit is listed last, without a rank or score.
+    assert!(lines[3].starts_with("limited,,"));
+    assert!(lines[3].ends_with(",Found 1 interesting results (limit 1)"));
+
+    let mut cmd = Command::cargo_bin("bts")?;
+    cmd.arg("summarize")
+        .arg("./config-tests/summary.json")
+        .arg("--format")
+        .arg("json");
+
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let rows: serde_json::Value = serde_json::from_slice(&output)?;
+    let flaky = rows
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|row| row["name"] == "flaky")
+        .unwrap();
+    assert!((flaky["expected_time_to_next_ms"].as_f64().unwrap() - 500.0).abs() < 1e-6);
+    assert!(flaky["time_to_next_low_ms"].as_f64().unwrap() < 500.0);
+    assert!(flaky["time_to_next_high_ms"].as_f64().unwrap() > 500.0);
+
+    Ok(())
+}
+
+#[test]
+fn next_prints_without_running() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+    cmd.arg("next")
+        .arg("./config-tests/limit-reached.json")
+        .arg("-n")
+        .arg("5")
+        .arg("--json");
+
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let scripts: serde_json::Value = serde_json::from_slice(&output)?;
+    let mut names = scripts
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|script| script["name"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, vec!["exit 0", "exit 2"]);
+
+    let mut cmd = Command::cargo_bin("bts")?;
+    cmd.arg("next").arg("./config-tests/limit-reached.json");
+    let output = String::from_utf8(cmd.assert().success().get_output().stdout.clone())?;
+    assert_eq!(output.lines().count(), 1);
+    assert!(output.contains("\t./scripts/exit.sh "));
+
+    Ok(())
+}
+
+#[test]
+fn record_applies_results() -> Result<(), Box<dyn std::error::Error>> {
+    std::fs::copy(
+        "./config-tests/limit-reached.json",
+        "./tests/temp/record.json",
+    )?;
+
+    for result in ["--interesting", "--uninteresting", "--uninteresting"] {
+        let mut cmd = Command::cargo_bin("bts")?;
+        cmd.arg("record")
+            .arg("./tests/temp/record.json")
+            .arg("exit 2")
+            .arg(result)
+            .arg("--runtime-ms")
+            .arg("20");
+        cmd.assert().success();
+    }
+
+    let config: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string("./tests/temp/record.json")?)?;
+    let script = &config["scripts"][2];
+    assert_eq!(script["runcount"], 3);
+    assert_eq!(script["results"]["interesting"], 1);
+    assert_eq!(script["results"]["uninteresting"], 2);
+    assert_eq!(script["avgruntime_ms"], 20.0);
+    assert!(script.get("outcomes").is_none());
+    let history = config["history"].as_array().unwrap();
+    assert_eq!(history.len(), 3);
+    assert_eq!(history[0]["script"], "exit 2");
+    assert_eq!(history[0]["verdict"], "interesting");
+    assert_eq!(history[2]["verdict"], "uninteresting");
+
+    let mut cmd = Command::cargo_bin("bts")?;
+    cmd.arg("record")
+        .arg("./tests/temp/record.json")
+        .arg("missing")
+        .arg("--interesting")
+        .arg("--runtime-ms")
+        .arg("20");
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("ERROR: Unknown script missing"));
+
+    let mut cmd = Command::cargo_bin("bts")?;
+    cmd.arg("record")
+        .arg("./tests/temp/record.json")
+        .arg("exit 2")
+        .arg("--interesting")
+        .arg("--runtime-ms")
+        .arg("inf");
+    cmd.assert().failure().stdout(predicate::str::contains(
+        "ERROR: --runtime-ms must be a finite number of at least 0",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn concurrent_records_are_all_kept() -> Result<(), Box<dyn std::error::Error>> {
+    std::fs::copy(
+        "./config-tests/limit-reached.json",
+        "./tests/temp/record-concurrent.json",
+    )?;
+
+    let children = (0..8)
+        .map(|_| {
+            Command::cargo_bin("bts")
+                .unwrap()
+                .arg("record")
+                .arg("./tests/temp/record-concurrent.json")
+                .arg("exit 2")
+                .arg("--uninteresting")
+                .arg("--runtime-ms")
+                .arg("20")
+                .stdout(std::process::Stdio::null())
+                .spawn()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    for mut child in children {
+        assert!(child.wait()?.success());
+    }
+
+    let config: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(
+        "./tests/temp/record-concurrent.json",
+    )?)?;
+    assert_eq!(config["scripts"][2]["runcount"], 8);
+
+    Ok(())
+}
+
+#[test]
+fn rank_by_best_arm_is_deterministic() -> Result<(), Box<dyn std::error::Error>> {
+    let rank = || -> Result<String, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("bts")?;
+        cmd.arg("rank")
+            .arg("./config-tests/limit-reached.json")
+            .arg("--samples")
+            .arg("500");
+        Ok(String::from_utf8(
+            cmd.assert().success().get_output().stdout.clone(),
+        )?)
+    };
+
+    let first = rank()?;
+    assert_eq!(first, rank()?);
+    assert_eq!(first.lines().count(), 2);
+    assert!(first.lines().all(|line| line.contains("% (95% CI ")));
+    assert!(!first.contains("limited"));
+
+    Ok(())
+}
+
+#[test]
+fn summarize_time_to_next() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("summarize").arg("./config-tests/summary.json");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("- Posterior mean: 0.1000"))
+        .stdout(predicate::str::contains("- 5th and 95th percentiles: 0.0"))
+        .stdout(predicate::str::contains(
+            "- Expected time to next interesting case: 500.0ms (90% interval ",
+        ))
+        // Without runs, there is no runtime to estimate the time from.
+        .stdout(predicate::str::contains("- Posterior mean: 0.5000"))
+        .stdout(predicate::str::contains("interesting case").count(1));
+
+    Ok(())
+}
+
+#[test]
+fn plot_top_scripts() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    // Fewer scripts than the default of three still plot.
+    cmd.arg("summarize").arg("./config-tests/summary.json");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Top 2 scripts by runs:"));
+
+    let mut cmd = Command::cargo_bin("bts")?;
+    cmd.arg("summarize")
+        .arg("./config-tests/summary.json")
+        .arg("--top")
+        .arg("1")
+        .arg("--plot-by")
+        .arg("name=ne");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Top 1 scripts by runs, named *ne*:",
+        ))
+        .stdout(predicate::str::contains("1: 0 "));
+
+    let mut cmd = Command::cargo_bin("bts")?;
+    cmd.arg("summarize")
+        .arg("./config-tests/summary.json")
+        .arg("--plot-by")
+        .arg("name=missing");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("No scripts to plot."));
+
+    Ok(())
+}
+
+#[test]
+fn plot_curves() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("summarize").arg("./config-tests/summary.json");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Posterior density of each failure rate.",
+        ))
+        .stdout(predicate::str::contains("Density of each score"))
+        .stdout(predicate::str::contains("x: failure rate from "))
+        .stdout(predicate::str::contains("x: score from "));
+
+    let mut cmd = Command::cargo_bin("bts")?;
+    cmd.arg("summarize")
+        .arg("./config-tests/summary.json")
+        .arg("--plot")
+        .arg("cdf");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("y: cumulative probability"))
+        .stdout(predicate::str::contains("Plot of top").count(1));
+
+    let mut cmd = Command::cargo_bin("bts")?;
+    cmd.arg("summarize")
+        .arg("./config-tests/summary.json")
+        .arg("--plot")
+        .arg("histogram");
+    cmd.assert().failure();
+
+    Ok(())
+}
+
+#[test]
+fn history_plot() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("history-plot").arg("./config-tests/history.json");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Interesting results found so far.",
+        ))
+        .stdout(predicate::str::contains("Share of the runs so far."))
+        .stdout(predicate::str::contains("Posterior median failure rate."))
+        .stdout(predicate::str::contains("x: runs from 0 to 4"))
+        .stdout(predicate::str::contains("Top 2 scripts by runs:"));
+
+    let mut cmd = Command::cargo_bin("bts")?;
+    cmd.arg("history-plot")
+        .arg("./config-tests/history.json")
+        .arg("--wall-clock");
+    cmd.assert().success().stdout(predicate::str::contains(
+        "x: seconds since the first run from 0 to 3",
+    ));
+
+    let mut cmd = Command::cargo_bin("bts")?;
+    cmd.arg("history-plot").arg("./config-tests/summary.json");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("No run history to plot."));
+
+    Ok(())
+}
+
+#[test]
+fn history_limit_drops_oldest_runs() -> Result<(), Box<dyn std::error::Error>> {
+    let mut config: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string("./config-tests/history.json")?)?;
+    config["history_limit"] = 2.into();
+    std::fs::write(
+        "./tests/temp/history-limit.json",
+        serde_json::to_string(&config)?,
+    )?;
+
+    let mut cmd = Command::cargo_bin("bts")?;
+    cmd.arg("record")
+        .arg("./tests/temp/history-limit.json")
+        .arg("steady")
+        .arg("--uninteresting")
+        .arg("--runtime-ms")
+        .arg("10");
+    cmd.assert().success();
+
+    let config: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string("./tests/temp/history-limit.json")?)?;
+    let history = config["history"].as_array().unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0]["verdict"], "error");
+    assert_eq!(history[1]["script"], "steady");
+    assert_eq!(config["history_dropped"], 3);
+
+    let mut cmd = Command::cargo_bin("bts")?;
+    cmd.arg("history-plot")
+        .arg("./tests/temp/history-limit.json");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("x: runs from 3 to 5"));
+
+    Ok(())
+}
+
+#[test]
+fn report_html() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bts")?;
+
+    cmd.arg("report")
+        .arg("./config-tests/history.json")
+        .arg("--html")
+        .arg("./tests/temp/report.html")
+        .arg("--artifacts")
+        .arg("./tests/artifacts");
+    cmd.assert().success();
+
+    let report = std::fs::read_to_string("./tests/temp/report.html")?;
+    assert!(report.starts_with("<!DOCTYPE html>"));
+    assert!(report.contains("<table class=\"sortable\">"));
+    assert!(report.contains("<td>flaky</td>"));
+    assert!(report.contains("<td>steady</td>"));
+    assert!(report.contains(r#"<a href="../artifacts/flaky/crash.log">crash.log</a>"#));
+    assert!(report.contains("<h2>Allocation history</h2>"));
+    assert_eq!(report.matches("<svg ").count(), 4);
+    // Nothing is loaded from elsewhere, so the report works offline and as an attachment.
+    assert!(!report.contains("src="));
+    assert!(!report.contains("https://"));
+
+    Ok(())
+}